@@ -1,3 +1,704 @@
+use anyhow::{anyhow, Error, Result};
+use clap::{ArgEnum, Parser, Subcommand};
+use libips::actions::Manifest;
+use libips::image::{HistoryOutcome, Image, ImageError, VerifyIssue};
+use serde::Serialize;
+use std::ffi::OsString;
+use std::io::{stderr, stdout, Write};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+#[clap(propagate_version = true)]
+struct App {
+    /// Root of the image to operate on
+    #[clap(short = 'R', default_value = "/", global = true)]
+    image: PathBuf,
+
+    /// Output format, also used for errors
+    #[clap(short = 'o', long = "format", arg_enum, default_value = "table", global = true)]
+    output: OutputFormat,
+
+    #[clap(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// List the actions of installed packages
+    Contents {
+        /// Only list actions of these types, e.g. -t file,link
+        #[clap(short = 't', value_delimiter = ',')]
+        action_types: Vec<String>,
+
+        /// Packages to list, all installed packages if none are given
+        patterns: Vec<String>,
+    },
+    /// Show the operations done on the image, newest first
+    History {
+        /// Only show the last N operations
+        #[clap(short = 'n')]
+        limit: Option<usize>,
+    },
+    /// Compare the installed packages with the files on disk
+    Verify {
+        /// Packages to verify, all installed packages if none are given
+        patterns: Vec<String>,
+    },
+    /// Remove installed packages from the image
+    Uninstall {
+        /// Also remove files marked preserve
+        #[clap(long)]
+        force: bool,
+
+        /// Only show what would be removed
+        #[clap(long)]
+        dry_run: bool,
+
+        #[clap(required = true)]
+        patterns: Vec<String>,
+    },
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+enum OutputFormat {
+    Table,
+    Json,
+    Tsv,
+}
+
+const CONTENTS_ACTION_TYPES: [&str; 5] = ["file", "dir", "link", "depend", "license"];
+
 fn main() {
+    std::process::exit(run(std::env::args_os(), &mut stdout()));
+}
+
+// Runs pkg6 with the given command line and returns its exit code. Errors
+// are written to out as json with --format json, to stderr otherwise.
+fn run<I, T, W>(args: I, out: &mut W) -> i32
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+    W: Write,
+{
+    let cli = match App::try_parse_from(args) {
+        Ok(cli) => cli,
+        Err(e) => e.exit(),
+    };
+
+    let res = Image::open(&cli.image)
+        .map_err(|e| {
+            Error::new(e).context(format!("could not open image {}", cli.image.display()))
+        })
+        .and_then(|image| match &cli.command {
+            Commands::Contents {
+                action_types,
+                patterns,
+            } => contents(&image, patterns, action_types, cli.output, out),
+            Commands::History { limit } => history(&image, *limit, cli.output, out),
+            Commands::Verify { patterns } => verify(&image, patterns, cli.output, out),
+            Commands::Uninstall {
+                force,
+                dry_run,
+                patterns,
+            } => uninstall(&image, patterns, *force, *dry_run, cli.output, out),
+        });
+
+    match res {
+        Ok(()) => 0,
+        Err(e) if cli.output == OutputFormat::Json => {
+            let _ = writeln!(out, "{}", error_json(&e));
+            1
+        }
+        Err(e) => {
+            let _ = writeln!(stderr(), "Error: {:#}", e);
+            1
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorOutput {
+    code: &'static str,
+    message: String,
+}
+
+fn error_json(err: &Error) -> String {
+    let code = match err.downcast_ref::<ImageError>() {
+        Some(ImageError::IO(_)) => "io-error",
+        Some(ImageError::Json(_)) => "invalid-image-data",
+        Some(ImageError::PackageNotInstalled(_)) => "package-not-installed",
+        Some(ImageError::ServiceActionFailed { .. }) => "service-action-failed",
+        None => "error",
+    };
+    let output = ErrorOutput {
+        code,
+        message: format!("{:#}", err),
+    };
+    serde_json::to_string(&output).unwrap_or_default()
+}
+
+// One listed action of an installed package
+#[derive(Debug, PartialEq, Serialize)]
+struct ContentsEntry {
+    package: String,
+    action: String,
+    path: String,
+}
+
+fn contents<W: Write>(
+    image: &Image,
+    patterns: &[String],
+    action_types: &[String],
+    output: OutputFormat,
+    out: &mut W,
+) -> Result<()> {
+    if let Some(unknown) = action_types
+        .iter()
+        .find(|t| !CONTENTS_ACTION_TYPES.contains(&t.as_str()))
+    {
+        return Err(anyhow!(
+            "unknown action type {}, expected one of {}",
+            unknown,
+            CONTENTS_ACTION_TYPES.join(", ")
+        ));
+    }
+
+    let mut entries = vec![];
+    for fmri in image.installed_packages()? {
+        if !patterns.is_empty() && !patterns.iter().any(|p| fmri.matches_pattern(p)) {
+            continue;
+        }
+        let manifest = match image.installed_manifest(&fmri)? {
+            Some(manifest) => manifest,
+            None => continue,
+        };
+        for (action, path) in manifest_contents(&manifest) {
+            if action_types.is_empty() || action_types.iter().any(|t| t == action) {
+                entries.push(ContentsEntry {
+                    package: fmri.stem.clone(),
+                    action: action.to_owned(),
+                    path,
+                });
+            }
+        }
+    }
+
+    let rows: Vec<Vec<String>> = entries
+        .iter()
+        .map(|e| vec![e.package.clone(), e.action.clone(), e.path.clone()])
+        .collect();
+    match output {
+        OutputFormat::Json => writeln!(out, "{}", serde_json::to_string_pretty(&entries)?)?,
+        OutputFormat::Tsv => write_tsv(out, &rows)?,
+        OutputFormat::Table => write_table(out, &["PACKAGE", "ACTION", "PATH"], &rows)?,
+    }
+    Ok(())
+}
+
+// One difference between an installed package and the disk
+#[derive(Debug, PartialEq, Serialize)]
+struct VerifyEntry {
+    fmri: String,
+    path: String,
+    problem: String,
+}
+
+// Lists the differences found and fails if there are any
+fn verify<W: Write>(
+    image: &Image,
+    patterns: &[String],
+    output: OutputFormat,
+    out: &mut W,
+) -> Result<()> {
+    let issues: Vec<VerifyIssue> = image
+        .verify()?
+        .into_iter()
+        .filter(|issue| {
+            patterns.is_empty() || patterns.iter().any(|p| issue.fmri.matches_pattern(p))
+        })
+        .collect();
+
+    match output {
+        OutputFormat::Json => {
+            let entries: Vec<VerifyEntry> = issues
+                .iter()
+                .map(|issue| VerifyEntry {
+                    fmri: issue.fmri.to_string(),
+                    path: issue.path.clone(),
+                    problem: issue.problem.to_string(),
+                })
+                .collect();
+            writeln!(out, "{}", serde_json::to_string_pretty(&entries)?)?;
+        }
+        OutputFormat::Tsv => {
+            for issue in &issues {
+                writeln!(out, "{}\t{}\t{}", issue.fmri, issue.path, issue.problem)?;
+            }
+        }
+        OutputFormat::Table => {
+            for issue in &issues {
+                writeln!(out, "{}", issue)?;
+            }
+        }
+    }
+
+    if !issues.is_empty() {
+        return Err(anyhow!("verification found {} problem(s)", issues.len()));
+    }
+    Ok(())
+}
+
+fn uninstall<W: Write>(
+    image: &Image,
+    patterns: &[String],
+    force: bool,
+    dry_run: bool,
+    output: OutputFormat,
+    out: &mut W,
+) -> Result<()> {
+    let installed = image.installed_packages()?;
+    let mut stems = vec![];
+    for pattern in patterns {
+        let mut matched = false;
+        for fmri in installed.iter().filter(|fmri| fmri.matches_pattern(pattern)) {
+            matched = true;
+            if !stems.contains(&fmri.stem) {
+                stems.push(fmri.stem.clone());
+            }
+        }
+        if !matched {
+            return Err(anyhow!("no installed package matches {}", pattern));
+        }
+    }
+
+    let reports = image.uninstall(&stems, force, dry_run)?;
+    if output == OutputFormat::Json {
+        writeln!(out, "{}", serde_json::to_string_pretty(&reports)?)?;
+        return Ok(());
+    }
+
+    let mut rows: Vec<Vec<String>> = vec![];
+    for report in &reports {
+        let result = |r: &str, paths: &[String]| {
+            paths
+                .iter()
+                .map(|path| vec![report.fmri.stem.clone(), r.to_owned(), path.clone()])
+                .collect::<Vec<_>>()
+        };
+        rows.extend(result("removed", &report.removed));
+        rows.extend(result("preserved", &report.preserved));
+    }
+    match output {
+        OutputFormat::Tsv => write_tsv(out, &rows)?,
+        _ => write_table(out, &["PACKAGE", "RESULT", "PATH"], &rows)?,
+    }
+    Ok(())
+}
+
+// The action type and the path, or what identifies the action instead
+fn manifest_contents(manifest: &Manifest) -> Vec<(&'static str, String)> {
+    let mut contents = vec![];
+    contents.extend(manifest.files.iter().map(|f| ("file", f.path.clone())));
+    contents.extend(manifest.directories.iter().map(|d| ("dir", d.path.clone())));
+    contents.extend(manifest.links.iter().map(|l| ("link", l.path.clone())));
+    contents.extend(
+        manifest
+            .dependencies
+            .iter()
+            .map(|d| ("depend", d.fmri.clone())),
+    );
+    contents.extend(
+        manifest
+            .licenses
+            .iter()
+            .map(|l| ("license", l.license.clone())),
+    );
+    contents
+}
+
+fn history<W: Write>(
+    image: &Image,
+    limit: Option<usize>,
+    output: OutputFormat,
+    out: &mut W,
+) -> Result<()> {
+    let entries = image.history(limit)?;
+    if output == OutputFormat::Json {
+        writeln!(out, "{}", serde_json::to_string_pretty(&entries)?)?;
+        return Ok(());
+    }
+
+    let rows: Vec<Vec<String>> = entries
+        .iter()
+        .map(|e| {
+            let outcome = match &e.outcome {
+                HistoryOutcome::Succeeded => String::from("Succeeded"),
+                HistoryOutcome::Failed(reason) => format!("Failed: {}", reason),
+            };
+            let fmris: Vec<String> = e.fmris.iter().map(|f| f.to_string()).collect();
+            vec![
+                format_timestamp(e.timestamp),
+                e.operation.clone(),
+                outcome,
+                fmris.join(" "),
+            ]
+        })
+        .collect();
+    match output {
+        OutputFormat::Tsv => write_tsv(out, &rows)?,
+        _ => write_table(out, &["TIME", "OPERATION", "OUTCOME", "PACKAGES"], &rows)?,
+    }
+    Ok(())
+}
+
+// Seconds since the unix epoch as an ISO 8601 UTC time
+fn format_timestamp(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let secs = timestamp % 86400;
+
+    // Civil date from days since 1970-01-01, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+fn write_tsv<W: Write>(out: &mut W, rows: &[Vec<String>]) -> Result<()> {
+    for row in rows {
+        writeln!(out, "{}", row.join("\t"))?;
+    }
+    Ok(())
+}
+
+// Columns are padded to their widest value, the last one is not padded
+fn write_table<W: Write>(out: &mut W, header: &[&str], rows: &[Vec<String>]) -> Result<()> {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.len()).collect();
+    for row in rows {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.len());
+        }
+    }
+
+    let header: Vec<String> = header.iter().map(|h| h.to_string()).collect();
+    for row in std::iter::once(&header).chain(rows) {
+        let mut line = String::new();
+        for (i, value) in row.iter().enumerate() {
+            if i + 1 == row.len() {
+                line.push_str(value);
+            } else {
+                line.push_str(&format!("{:width$} ", value, width = widths[i]));
+            }
+        }
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libips::fmri::Fmri;
+    use libips::digest::{Digest, DigestAlgorithm, DigestSource};
+    use libips::image::HistoryEntry;
+    use std::fs::Permissions;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    // An image in a temporary directory with the example package installed
+    fn example_image(dir: &std::path::Path) -> Image {
+        Image::new(dir).save().unwrap();
+        let image = Image::open(dir).unwrap();
+        let manifest = Manifest::parse_string(String::from(
+            "set name=pkg.fmri value=pkg://openindiana.org/web/server/nginx@1.18.0,5.11-2020.0.1.0
+dir path=etc owner=root group=sys mode=0755
+dir path=etc/nginx owner=root group=bin mode=0755
+dir path=usr owner=root group=sys mode=0755
+dir path=usr/bin owner=root group=bin mode=0755
+dir path=usr/sbin owner=root group=bin mode=0755
+dir path=usr/share owner=root group=sys mode=0755
+dir path=usr/share/doc owner=root group=bin mode=0755
+dir path=usr/share/doc/nginx owner=root group=bin mode=0755
+dir path=usr/share/man owner=root group=bin mode=0755
+dir path=usr/share/man/man8 owner=root group=bin mode=0755
+file 4b76e83bb4bb7c87176b72ef805fe78ecae60d2c path=usr/bin/nginx owner=root group=bin mode=0755
+file ab5eadc1d0d8b7a4e8a52c1a5b30e2fb8b3a25d4 path=usr/share/man/man8/nginx.8 owner=root group=bin mode=0644
+file d143ca7a6aac765d28724af54d969a4bd2202383 path=etc/nginx/nginx.conf owner=root group=bin mode=0644 preserve=true
+link path=usr/sbin/nginx target=../bin/nginx
+depend fmri=library/pcre type=require
+license 64d7ebb2aa14f7c2a8e4bd3d03a4e2d5e7b7e1e4 license=BSD",
+        ))
+        .unwrap();
+        let fmri = Fmri::parse("pkg://openindiana.org/web/server/nginx@1.18.0").unwrap();
+        image.installed_db().add(&fmri, &manifest, true).unwrap();
+        image
+    }
+
+    // Create the directories, files and links of the example package. The
+    // installed manifest is updated with the hashes of the written files.
+    fn lay_down_example(image: &Image, root: &std::path::Path) {
+        let fmri = image.installed_version("web/server/nginx").unwrap().unwrap();
+        let mut manifest = image.installed_manifest(&fmri).unwrap().unwrap();
+        let mode = |mode: &str| Permissions::from_mode(u32::from_str_radix(mode, 8).unwrap());
+        for dir in &manifest.directories {
+            std::fs::create_dir_all(root.join(&dir.path)).unwrap();
+            std::fs::set_permissions(root.join(&dir.path), mode(&dir.mode)).unwrap();
+        }
+        for file in manifest.files.iter_mut() {
+            std::fs::write(root.join(&file.path), &file.path).unwrap();
+            std::fs::set_permissions(root.join(&file.path), mode(&file.mode)).unwrap();
+            let digest = Digest::from_bytes(
+                file.path.as_bytes(),
+                DigestAlgorithm::SHA1,
+                DigestSource::PrimaryPayloadHash,
+            );
+            file.payload.as_mut().unwrap().primary_identifier = digest.unwrap();
+        }
+        for link in &manifest.links {
+            std::os::unix::fs::symlink(&link.target, root.join(&link.path)).unwrap();
+        }
+        image.installed_db().add(&fmri, &manifest, true).unwrap();
+    }
+
+    #[test]
+    fn list_installed_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = example_image(dir.path());
+
+        let mut out = vec![];
+        let res = contents(&image, &[], &[], OutputFormat::Tsv, &mut out);
+        assert!(res.is_ok(), "error listing contents: {:?}", res);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "web/server/nginx\tfile\tusr/bin/nginx
+web/server/nginx\tfile\tusr/share/man/man8/nginx.8
+web/server/nginx\tfile\tetc/nginx/nginx.conf
+web/server/nginx\tdir\tetc
+web/server/nginx\tdir\tetc/nginx
+web/server/nginx\tdir\tusr
+web/server/nginx\tdir\tusr/bin
+web/server/nginx\tdir\tusr/sbin
+web/server/nginx\tdir\tusr/share
+web/server/nginx\tdir\tusr/share/doc
+web/server/nginx\tdir\tusr/share/doc/nginx
+web/server/nginx\tdir\tusr/share/man
+web/server/nginx\tdir\tusr/share/man/man8
+web/server/nginx\tlink\tusr/sbin/nginx
+web/server/nginx\tdepend\tlibrary/pcre
+web/server/nginx\tlicense\tBSD
+"
+        );
+
+        let mut out = vec![];
+        let files = vec![String::from("file")];
+        let patterns = vec![String::from("nginx")];
+        contents(&image, &patterns, &files, OutputFormat::Table, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "PACKAGE          ACTION PATH
+web/server/nginx file   usr/bin/nginx
+web/server/nginx file   usr/share/man/man8/nginx.8
+web/server/nginx file   etc/nginx/nginx.conf
+"
+        );
+
+        let mut out = vec![];
+        contents(&image, &[], &files, OutputFormat::Json, &mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 3);
+        assert_eq!(json[0]["path"], "usr/bin/nginx");
+
+        let mut out = vec![];
+        let patterns = vec![String::from("vim")];
+        contents(&image, &patterns, &[], OutputFormat::Tsv, &mut out).unwrap();
+        assert!(out.is_empty());
+
+        let unknown = vec![String::from("hardware")];
+        assert!(contents(&image, &[], &unknown, OutputFormat::Tsv, &mut vec![]).is_err());
+    }
+
+    #[test]
+    fn list_image_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = example_image(dir.path());
+
+        let vim = Fmri::parse("pkg://openindiana.org/editor/vim@9.0.0").unwrap();
+        let install = HistoryEntry {
+            timestamp: 1660000000,
+            ..HistoryEntry::new("install", vec![vim.clone()], HistoryOutcome::Succeeded)
+        };
+        let uninstall = HistoryEntry {
+            timestamp: 1660003600,
+            ..HistoryEntry::new(
+                "uninstall",
+                vec![vim],
+                HistoryOutcome::Failed(String::from("package is in use")),
+            )
+        };
+        image.record_history(&install).unwrap();
+        image.record_history(&uninstall).unwrap();
+
+        let mut out = vec![];
+        let res = history(&image, None, OutputFormat::Table, &mut out);
+        assert!(res.is_ok(), "error listing history: {:?}", res);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "TIME                 OPERATION OUTCOME                   PACKAGES
+2022-08-09T00:06:40Z uninstall Failed: package is in use pkg://openindiana.org/editor/vim@9.0.0
+2022-08-08T23:06:40Z install   Succeeded                 pkg://openindiana.org/editor/vim@9.0.0
+"
+        );
+
+        let mut out = vec![];
+        history(&image, Some(1), OutputFormat::Tsv, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2022-08-09T00:06:40Z\tuninstall\tFailed: package is in use\t\
+             pkg://openindiana.org/editor/vim@9.0.0\n"
+        );
+
+        let mut out = vec![];
+        history(&image, Some(1), OutputFormat::Json, &mut out).unwrap();
+        let entries: Vec<HistoryEntry> = serde_json::from_slice(&out).unwrap();
+        assert_eq!(entries, vec![uninstall]);
+    }
+
+    #[test]
+    fn errors_as_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        let mut out = vec![];
+        let code = run(
+            ["pkg6", "-R", missing.to_str().unwrap(), "contents", "--format", "json"],
+            &mut out,
+        );
+        assert_eq!(code, 1);
+        let error: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(error["code"], "io-error");
+        assert!(error["message"]
+            .as_str()
+            .unwrap()
+            .starts_with(&format!("could not open image {}: ", missing.display())));
+
+        example_image(dir.path());
+        let root = dir.path().to_str().unwrap();
+        let mut out = vec![];
+        let code = run(["pkg6", "-R", root, "-o", "json", "contents", "-t", "hardware"], &mut out);
+        assert_eq!(code, 1);
+        let error: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(error["code"], "error");
+        assert!(error["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("unknown action type hardware"));
+
+        let mut out = vec![];
+        assert_eq!(run(["pkg6", "-R", root, "contents", "-o", "tsv"], &mut out), 0);
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with("web/server/nginx\tfile\tusr/bin/nginx\n"));
+    }
+
+    #[test]
+    fn uninstall_example_package() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = example_image(dir.path());
+        lay_down_example(&image, dir.path());
+        let root = dir.path().to_str().unwrap();
+
+        let mut out = vec![];
+        let args = ["pkg6", "-R", root, "-o", "tsv", "uninstall", "--dry-run", "nginx"];
+        let code = run(args, &mut out);
+        assert_eq!(code, 0);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "web/server/nginx\tremoved\tusr/bin/nginx
+web/server/nginx\tremoved\tusr/share/man/man8/nginx.8
+web/server/nginx\tremoved\tusr/sbin/nginx
+web/server/nginx\tremoved\tusr/share/doc/nginx
+web/server/nginx\tremoved\tusr/share/man/man8
+web/server/nginx\tremoved\tusr/share/doc
+web/server/nginx\tremoved\tusr/share/man
+web/server/nginx\tremoved\tusr/bin
+web/server/nginx\tremoved\tusr/sbin
+web/server/nginx\tremoved\tusr/share
+web/server/nginx\tremoved\tusr
+web/server/nginx\tpreserved\tetc/nginx/nginx.conf
+"
+        );
+        assert!(dir.path().join("usr/bin/nginx").exists());
+        assert_eq!(image.installed_packages().unwrap().len(), 1);
+
+        let mut out = vec![];
+        assert_eq!(run(["pkg6", "-R", root, "uninstall", "--force", "nginx"], &mut out), 0);
+        assert!(image.installed_packages().unwrap().is_empty());
+        assert_eq!(image.history(None).unwrap()[0].operation, "uninstall");
+
+        // Only the image metadata is left
+        let mut left: Vec<String> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        left.sort();
+        assert_eq!(left, vec!["pkg6.image.json", "pkg6.installed.json", "var"]);
+
+        let mut out = vec![];
+        assert_eq!(run(["pkg6", "-R", root, "uninstall", "nginx"], &mut out), 1);
+    }
+
+    #[test]
+    fn verify_installed_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = example_image(dir.path());
+        lay_down_example(&image, dir.path());
+        // Resolve the owners and groups of the package to whoever runs the test
+        let meta = dir.path().metadata().unwrap();
+        std::fs::write(
+            dir.path().join("etc/passwd"),
+            format!("root:x:{}:{}::/root:/bin/sh\n", meta.uid(), meta.gid()),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("etc/group"),
+            format!("sys::{0}:\nbin::{0}:\n", meta.gid()),
+        )
+        .unwrap();
+        let root = dir.path().to_str().unwrap();
+
+        let mut out = vec![];
+        assert_eq!(run(["pkg6", "-R", root, "verify"], &mut out), 0);
+        assert!(out.is_empty());
+
+        let nginx = dir.path().join("usr/bin/nginx");
+        std::fs::set_permissions(&nginx, Permissions::from_mode(0o700)).unwrap();
 
+        let mut out = vec![];
+        let res = verify(&image, &[], OutputFormat::Table, &mut out);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "pkg://openindiana.org/web/server/nginx@1.18.0 usr/bin/nginx: \
+             mode is 0700, expected 0755\n"
+        );
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            "verification found 1 problem(s)"
+        );
+        assert_eq!(run(["pkg6", "-R", root, "verify", "nginx"], &mut vec![]), 1);
+        assert_eq!(run(["pkg6", "-R", root, "verify", "vim"], &mut vec![]), 0);
+    }
 }
@@ -5,13 +5,16 @@
 
 // Source https://docs.oracle.com/cd/E23824_01/html/E21796/pkg-5.html
 
-use crate::digest::Digest;
+use crate::digest::{Digest, DigestAlgorithm, DigestSource};
+use crate::fmri::Version;
 use crate::payload::{Payload, PayloadError};
 use pest::Parser;
 use pest_derive::Parser;
 use std::clone::Clone;
 use std::collections::HashMap;
+use std::fmt::Display;
 use std::fs::read_to_string;
+use std::io::Write;
 use std::path::Path;
 use std::result::Result as StdResult;
 use std::str::FromStr;
@@ -47,12 +50,12 @@ pub trait FacetedAction {
     fn remove_facet(&mut self, facet: Facet) -> bool;
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct Action {
-    kind: ActionKind,
+    pub(crate) kind: ActionKind,
     payload: Payload,
-    payload_string: String,
-    properties: Vec<Property>,
+    pub(crate) payload_string: String,
+    pub(crate) properties: Vec<Property>,
     facets: HashMap<String, Facet>,
 }
 
@@ -66,14 +69,76 @@ impl Action {
             facets: HashMap::new(),
         }
     }
+
+    // The name of the action as written in a manifest
+    pub fn name(&self) -> &str {
+        match &self.kind {
+            ActionKind::Attr => "set",
+            ActionKind::Dir => "dir",
+            ActionKind::File => "file",
+            ActionKind::Dependency => "depend",
+            ActionKind::User => "user",
+            ActionKind::Group => "group",
+            ActionKind::Driver => "driver",
+            ActionKind::License => "license",
+            ActionKind::Link => "link",
+            ActionKind::Legacy => "legacy",
+            ActionKind::Transform => "<transform",
+            ActionKind::Unknown { action } => action,
+        }
+    }
+
+    // An action tagged with a variant is only selected if one of its values
+    // for that variant is the one selected. Unset variants do not filter.
+    fn matches_variants(&self, variants: &HashMap<String, String>) -> bool {
+        variants.iter().all(|(name, selected)| {
+            let values = self.get_values(name);
+            values.is_empty() || values.contains(&selected.as_str())
+        })
+    }
+
+    // An action tagged with facets is selected if any of them is enabled.
+    fn matches_facets(&self, facets: &HashMap<String, bool>) -> bool {
+        let mut tagged = self
+            .properties
+            .iter()
+            .filter(|p| is_facet(p.key.clone()) && string_to_bool(&p.value).unwrap_or_default())
+            .peekable();
+        tagged.peek().is_none()
+            || tagged.any(|p| *facets.get(&get_facet_key(p.key.clone())).unwrap_or(&true))
+    }
+
+    // All values of the attribute key in the order they appear
+    pub fn get_values(&self, key: &str) -> Vec<&str> {
+        self.properties
+            .iter()
+            .filter(|p| p.key == key)
+            .map(|p| p.value.as_str())
+            .collect()
+    }
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())?;
+        if !self.payload_string.is_empty() {
+            write!(f, " {}", quote_value(&self.payload_string))?;
+        }
+        for prop in &self.properties {
+            write!(f, " {}={}", prop.key, quote_value(&prop.value))?;
+        }
+        Ok(())
+    }
 }
 
 impl FacetedAction for Action {
     fn add_facet(&mut self, facet: Facet) -> bool {
+        let facet = facet.normalize();
         self.facets.insert(facet.name.clone(), facet).is_none()
     }
 
     fn remove_facet(&mut self, facet: Facet) -> bool {
+        let facet = facet.normalize();
         self.facets.remove(&facet.name) == Some(facet)
     }
 }
@@ -86,9 +151,13 @@ pub struct Dir {
     pub path: String,
     pub group: String,
     pub owner: String,
+    // Numeric ids when owner/group are given as numbers rather than names
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
     pub mode: String, //TODO implement as bitmask
     pub revert_tag: String,
     pub salvage_from: String,
+    pub properties: Vec<Property>,
     pub facets: HashMap<String, Facet>,
 }
 
@@ -106,14 +175,22 @@ impl From<Action> for Dir {
         for prop in props {
             match prop.key.as_str() {
                 "path" => dir.path = prop.value,
-                "owner" => dir.owner = prop.value,
-                "group" => dir.group = prop.value,
+                "owner" => {
+                    dir.uid = prop.value.parse().ok();
+                    dir.owner = prop.value
+                }
+                "group" => {
+                    dir.gid = prop.value.parse().ok();
+                    dir.group = prop.value
+                }
                 "mode" => dir.mode = prop.value,
                 "revert-tag" => dir.revert_tag = prop.value,
                 "salvage-from" => dir.salvage_from = prop.value,
                 _ => {
                     if is_facet(prop.key.clone()) {
                         dir.add_facet(Facet::from_key_value(prop.key, prop.value));
+                    } else {
+                        dir.properties.push(prop);
                     }
                 }
             }
@@ -122,12 +199,29 @@ impl From<Action> for Dir {
     }
 }
 
+impl From<Dir> for Action {
+    fn from(dir: Dir) -> Self {
+        let mut act = Action::new(ActionKind::Dir);
+        push_property(&mut act.properties, "path", dir.path);
+        push_property(&mut act.properties, "owner", dir.owner);
+        push_property(&mut act.properties, "group", dir.group);
+        push_property(&mut act.properties, "mode", dir.mode);
+        push_property(&mut act.properties, "revert-tag", dir.revert_tag);
+        push_property(&mut act.properties, "salvage-from", dir.salvage_from);
+        act.properties.append(&mut facet_properties(dir.facets));
+        act.properties.extend(dir.properties);
+        act
+    }
+}
+
 impl FacetedAction for Dir {
     fn add_facet(&mut self, facet: Facet) -> bool {
+        let facet = facet.normalize();
         self.facets.insert(facet.name.clone(), facet).is_none()
     }
 
     fn remove_facet(&mut self, facet: Facet) -> bool {
+        let facet = facet.normalize();
         self.facets.remove(&facet.name) == Some(facet)
     }
 }
@@ -141,12 +235,16 @@ pub struct File {
     pub path: String,
     pub group: String,
     pub owner: String,
+    // Numeric ids when owner/group are given as numbers rather than names
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
     pub mode: String, //TODO implement as bitmask
     pub preserve: bool,
     pub overlay: bool,
     pub original_name: String,
     pub revert_tag: String,
     pub sys_attr: String,
+    pub actuators: Actuators,
     pub properties: Vec<Property>,
     pub facets: HashMap<String, Facet>,
 }
@@ -197,33 +295,52 @@ impl From<Action> for File {
                     });
                 }
             } else {
-                p.primary_identifier = Digest::from_str(&act.payload_string).unwrap();
+                match Digest::from_str(&act.payload_string) {
+                    Ok(digest) => p.primary_identifier = digest,
+                    Err(_) => file.properties.push(Property {
+                        key: "original-path".to_string(),
+                        value: act.payload_string,
+                    }),
+                }
             }
         }
         for prop in props {
             match prop.key.as_str() {
                 "path" => file.path = prop.value,
-                "owner" => file.owner = prop.value,
-                "group" => file.group = prop.value,
+                "owner" => {
+                    file.uid = prop.value.parse().ok();
+                    file.owner = prop.value
+                }
+                "group" => {
+                    file.gid = prop.value.parse().ok();
+                    file.group = prop.value
+                }
                 "mode" => file.mode = prop.value,
                 "revert-tag" => file.revert_tag = prop.value,
                 "original_name" => file.original_name = prop.value,
                 "sysattr" => file.sys_attr = prop.value,
-                "overlay" => {
-                    file.overlay = match string_to_bool(&prop.value) {
-                        Ok(b) => b,
-                        _ => false,
+                // overlay=allow and preserve=renamenew and friends are not
+                // booleans, keep them as written
+                "overlay" => match string_to_bool(&prop.value) {
+                    Ok(b) => file.overlay = b,
+                    Err(_) => file.properties.push(prop),
+                },
+                "preserve" => match string_to_bool(&prop.value) {
+                    Ok(b) => file.preserve = b,
+                    Err(_) => {
+                        file.preserve = true;
+                        file.properties.push(prop)
                     }
-                }
-                "preserve" => {
-                    file.preserve = match string_to_bool(&prop.value) {
-                        Ok(b) => b,
-                        _ => false,
+                },
+                "restart_fmri" => file.actuators.restart_fmri.push(prop.value),
+                "refresh_fmri" => file.actuators.refresh_fmri.push(prop.value),
+                "disable_fmri" => file.actuators.disable_fmri.push(prop.value),
+                "suspend_fmri" => file.actuators.suspend_fmri.push(prop.value),
+                "hash" | "chash" | "pkg.content-hash" | "pkg.hash" => {
+                    if let Some(prop) = add_payload_hash(&mut p, prop) {
+                        file.properties.push(prop);
                     }
                 }
-                "chash" | "pkg.content-hash" => p
-                    .additional_identifiers
-                    .push(Digest::from_str(&prop.value).unwrap()),
                 _ => {
                     if is_facet(prop.key.clone()) {
                         file.add_facet(Facet::from_key_value(prop.key, prop.value));
@@ -245,16 +362,86 @@ impl From<Action> for File {
     }
 }
 
+impl From<File> for Action {
+    fn from(mut file: File) -> Self {
+        let mut act = Action::new(ActionKind::File);
+        match &file.payload {
+            Some(payload) => act.payload_string = payload.primary_identifier.to_payload_string(),
+            // A positional path of the file in the proto area is written back
+            // in front of the attributes
+            None => {
+                if let Some(i) = file.properties.iter().position(|p| p.key == "original-path") {
+                    act.payload_string = file.properties.remove(i).value;
+                }
+            }
+        }
+        push_property(&mut act.properties, "path", file.path);
+        push_property(&mut act.properties, "owner", file.owner);
+        push_property(&mut act.properties, "group", file.group);
+        push_property(&mut act.properties, "mode", file.mode);
+        if file.preserve && !file.properties.iter().any(|p| p.key == "preserve") {
+            push_property(&mut act.properties, "preserve", "true".to_owned());
+        }
+        if file.overlay {
+            push_property(&mut act.properties, "overlay", "true".to_owned());
+        }
+        push_property(&mut act.properties, "original_name", file.original_name);
+        push_property(&mut act.properties, "revert-tag", file.revert_tag);
+        push_property(&mut act.properties, "sysattr", file.sys_attr);
+        for (key, values) in [
+            ("restart_fmri", file.actuators.restart_fmri),
+            ("refresh_fmri", file.actuators.refresh_fmri),
+            ("disable_fmri", file.actuators.disable_fmri),
+            ("suspend_fmri", file.actuators.suspend_fmri),
+        ] {
+            for value in values {
+                push_property(&mut act.properties, key, value);
+            }
+        }
+        if let Some(payload) = file.payload {
+            act.properties
+                .append(&mut payload_hash_properties(payload));
+        }
+        act.properties.append(&mut facet_properties(file.facets));
+        act.properties.extend(file.properties);
+        act
+    }
+}
+
 impl FacetedAction for File {
     fn add_facet(&mut self, facet: Facet) -> bool {
+        let facet = facet.normalize();
         self.facets.insert(facet.name.clone(), facet).is_none()
     }
 
     fn remove_facet(&mut self, facet: Facet) -> bool {
+        let facet = facet.normalize();
         self.facets.remove(&facet.name) == Some(facet)
     }
 }
 
+// Actuators name SMF services that need to be acted upon once the action
+// carrying them has been installed, updated or removed.
+#[derive(Debug, Default, PartialEq, Clone, Deserialize, Serialize, Diff)]
+#[diff(attr(
+    #[derive(Debug, PartialEq)]
+))]
+pub struct Actuators {
+    pub restart_fmri: Vec<String>,
+    pub refresh_fmri: Vec<String>,
+    pub disable_fmri: Vec<String>,
+    pub suspend_fmri: Vec<String>,
+}
+
+impl Actuators {
+    pub fn is_empty(&self) -> bool {
+        self.restart_fmri.is_empty()
+            && self.refresh_fmri.is_empty()
+            && self.disable_fmri.is_empty()
+            && self.suspend_fmri.is_empty()
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum FileError {
     #[error("file path is not a string")]
@@ -288,7 +475,8 @@ impl From<Action> for Dependency {
         }
         for prop in props {
             match prop.key.as_str() {
-                "fmri" => dep.fmri = prop.value,
+                // require-any carries several fmri, keep the others as they are
+                "fmri" if dep.fmri.is_empty() => dep.fmri = prop.value,
                 "type" => dep.dependency_type = prop.value,
                 "predicate" => dep.predicate = prop.value,
                 "root-image" => dep.root_image = prop.value,
@@ -305,12 +493,27 @@ impl From<Action> for Dependency {
     }
 }
 
+impl From<Dependency> for Action {
+    fn from(dep: Dependency) -> Self {
+        let mut act = Action::new(ActionKind::Dependency);
+        push_property(&mut act.properties, "fmri", dep.fmri);
+        push_property(&mut act.properties, "type", dep.dependency_type);
+        push_property(&mut act.properties, "predicate", dep.predicate);
+        push_property(&mut act.properties, "root-image", dep.root_image);
+        act.properties.append(&mut facet_properties(dep.facets));
+        act.properties.extend(dep.optional);
+        act
+    }
+}
+
 impl FacetedAction for Dependency {
     fn add_facet(&mut self, facet: Facet) -> bool {
+        let facet = facet.normalize();
         self.facets.insert(facet.name.clone(), facet).is_none()
     }
 
     fn remove_facet(&mut self, facet: Facet) -> bool {
+        let facet = facet.normalize();
         self.facets.remove(&facet.name) == Some(facet)
     }
 }
@@ -330,6 +533,21 @@ impl Facet {
             name: get_facet_key(key),
             value,
         }
+        .normalize()
+    }
+
+    // The facet name without surrounding whitespace or facet. prefix. Case is
+    // kept as facets like locale.en_US are case sensitive.
+    pub fn normalized_key(&self) -> String {
+        let name = self.name.trim();
+        name.strip_prefix("facet.").unwrap_or(name).trim().to_owned()
+    }
+
+    fn normalize(self) -> Facet {
+        Facet {
+            name: self.normalized_key(),
+            value: self.value.trim().to_owned(),
+        }
     }
 }
 
@@ -340,7 +558,7 @@ impl Facet {
 pub struct Attr {
     pub key: String,
     pub values: Vec<String>,
-    pub properties: HashMap<String, Property>,
+    pub properties: Vec<Property>,
 }
 
 impl From<Action> for Attr {
@@ -358,52 +576,99 @@ impl From<Action> for Attr {
             match prop.key.as_str() {
                 "name" => attr.key = prop.value,
                 "value" => attr.values.push(prop.value),
-                _ => {
-                    attr.properties.insert(
-                        prop.key.clone(),
-                        Property {
-                            key: prop.key,
-                            value: prop.value,
-                        },
-                    );
-                }
+                _ => attr.properties.push(prop),
             }
         }
         attr
     }
 }
 
+impl From<Attr> for Action {
+    fn from(attr: Attr) -> Self {
+        let mut act = Action::new(ActionKind::Attr);
+        push_property(&mut act.properties, "name", attr.key);
+        for value in attr.values {
+            act.properties.push(Property {
+                key: "value".to_owned(),
+                value,
+            });
+        }
+        act.properties.extend(attr.properties);
+        act
+    }
+}
+
 #[derive(Debug, Default, PartialEq, Clone, Deserialize, Serialize, Diff)]
 #[diff(attr(
     #[derive(Debug, PartialEq)]
 ))]
 pub struct License {
-    pub payload: String,
-    pub properties: HashMap<String, Property>,
+    pub payload: Option<Payload>,
+    pub license: String,
+    pub must_accept: bool,
+    pub must_display: bool,
+    pub properties: Vec<Property>,
 }
 
 impl From<Action> for License {
     fn from(act: Action) -> Self {
         let mut license = License::default();
+        let mut p = act.payload.clone();
         if !act.payload_string.is_empty() {
-            license.payload = act.payload_string;
+            match Digest::from_str(&act.payload_string) {
+                Ok(digest) => p.primary_identifier = digest,
+                Err(_) => license.properties.push(Property {
+                    key: "original-path".to_string(),
+                    value: act.payload_string,
+                }),
+            }
         }
         for prop in act.properties {
-            let key = prop.key.as_str();
-            {
-                license.properties.insert(
-                    key.to_owned(),
-                    Property {
-                        key: prop.key,
-                        value: prop.value,
-                    },
-                );
+            match prop.key.as_str() {
+                "license" => license.license = prop.value,
+                "must-accept" => {
+                    license.must_accept = string_to_bool(&prop.value).unwrap_or_default()
+                }
+                "must-display" => {
+                    license.must_display = string_to_bool(&prop.value).unwrap_or_default()
+                }
+                "chash" | "pkg.content-hash" | "pkg.hash" => {
+                    if let Some(prop) = add_payload_hash(&mut p, prop) {
+                        license.properties.push(prop);
+                    }
+                }
+                _ => license.properties.push(prop),
             }
         }
+        if p.primary_identifier.hash.is_empty() {
+            license.payload = None;
+        } else {
+            license.payload = Some(p);
+        }
         license
     }
 }
 
+impl From<License> for Action {
+    fn from(license: License) -> Self {
+        let mut act = Action::new(ActionKind::License);
+        push_property(&mut act.properties, "license", license.license);
+        if license.must_accept {
+            push_property(&mut act.properties, "must-accept", "true".to_owned());
+        }
+        if license.must_display {
+            push_property(&mut act.properties, "must-display", "true".to_owned());
+        }
+        if let Some(payload) = license.payload {
+            act.payload_string = payload.primary_identifier.to_payload_string();
+            act.properties
+                .append(&mut payload_hash_properties(payload));
+        }
+        act.properties.extend(license.properties);
+        act
+    }
+}
+
 #[derive(Debug, Default, PartialEq, Clone, Deserialize, Serialize, Diff)]
 #[diff(attr(
     #[derive(Debug, PartialEq)]
@@ -411,7 +676,7 @@ impl From<Action> for License {
 pub struct Link {
     pub path: String,
     pub target: String,
-    pub properties: HashMap<String, Property>,
+    pub properties: Vec<Property>,
 }
 
 impl From<Action> for Link {
@@ -429,21 +694,190 @@ impl From<Action> for Link {
             match prop.key.as_str() {
                 "path" => link.path = prop.value,
                 "target" => link.target = prop.value,
-                _ => {
-                    link.properties.insert(
-                        prop.key.clone(),
-                        Property {
-                            key: prop.key,
-                            value: prop.value,
-                        },
-                    );
-                }
+                _ => link.properties.push(prop),
             }
         }
         link
     }
 }
 
+impl From<Link> for Action {
+    fn from(link: Link) -> Self {
+        let mut act = Action::new(ActionKind::Link);
+        push_property(&mut act.properties, "path", link.path);
+        push_property(&mut act.properties, "target", link.target);
+        act.properties.extend(link.properties);
+        act
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Deserialize, Serialize, Diff)]
+#[diff(attr(
+    #[derive(Debug, PartialEq)]
+))]
+pub struct User {
+    pub username: String,
+    pub password: String,
+    pub uid: String,
+    pub group: String,
+    pub gcos_field: String,
+    pub home_dir: String,
+    pub login_shell: String,
+    pub group_list: Vec<String>,
+    pub ftpuser: bool,
+    pub properties: Vec<Property>,
+}
+
+impl From<Action> for User {
+    fn from(act: Action) -> Self {
+        let mut user = User::default();
+        let mut props = act.properties;
+        if !act.payload_string.is_empty() {
+            let p_str = split_property(act.payload_string);
+            props.push(Property {
+                key: p_str.0,
+                value: p_str.1,
+            })
+        }
+        for prop in props {
+            match prop.key.as_str() {
+                "username" => user.username = prop.value,
+                "password" => user.password = prop.value,
+                "uid" => user.uid = prop.value,
+                "group" => user.group = prop.value,
+                "gcos-field" => user.gcos_field = prop.value,
+                "home-dir" => user.home_dir = prop.value,
+                "login-shell" => user.login_shell = prop.value,
+                "group-list" => user.group_list.push(prop.value),
+                "ftpuser" => user.ftpuser = string_to_bool(&prop.value).unwrap_or_default(),
+                _ => user.properties.push(prop),
+            }
+        }
+        user
+    }
+}
+
+impl From<User> for Action {
+    fn from(user: User) -> Self {
+        let mut act = Action::new(ActionKind::User);
+        push_property(&mut act.properties, "username", user.username);
+        push_property(&mut act.properties, "password", user.password);
+        push_property(&mut act.properties, "uid", user.uid);
+        push_property(&mut act.properties, "group", user.group);
+        push_property(&mut act.properties, "gcos-field", user.gcos_field);
+        push_property(&mut act.properties, "home-dir", user.home_dir);
+        push_property(&mut act.properties, "login-shell", user.login_shell);
+        for group in user.group_list {
+            push_property(&mut act.properties, "group-list", group);
+        }
+        if user.ftpuser {
+            push_property(&mut act.properties, "ftpuser", "true".to_owned());
+        }
+        act.properties.extend(user.properties);
+        act
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Deserialize, Serialize, Diff)]
+#[diff(attr(
+    #[derive(Debug, PartialEq)]
+))]
+pub struct Group {
+    pub groupname: String,
+    pub gid: String,
+    pub password: String,
+    pub properties: Vec<Property>,
+}
+
+impl From<Action> for Group {
+    fn from(act: Action) -> Self {
+        let mut group = Group::default();
+        let mut props = act.properties;
+        if !act.payload_string.is_empty() {
+            let p_str = split_property(act.payload_string);
+            props.push(Property {
+                key: p_str.0,
+                value: p_str.1,
+            })
+        }
+        for prop in props {
+            match prop.key.as_str() {
+                "groupname" => group.groupname = prop.value,
+                "gid" => group.gid = prop.value,
+                "password" => group.password = prop.value,
+                _ => group.properties.push(prop),
+            }
+        }
+        group
+    }
+}
+
+impl From<Group> for Action {
+    fn from(group: Group) -> Self {
+        let mut act = Action::new(ActionKind::Group);
+        push_property(&mut act.properties, "groupname", group.groupname);
+        push_property(&mut act.properties, "gid", group.gid);
+        push_property(&mut act.properties, "password", group.password);
+        act.properties.extend(group.properties);
+        act
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Deserialize, Serialize, Diff)]
+#[diff(attr(
+    #[derive(Debug, PartialEq)]
+))]
+pub struct Driver {
+    pub name: String,
+    pub alias: Vec<String>,
+    pub class: String,
+    pub perms: Vec<String>,
+    pub policy: String,
+    pub properties: Vec<Property>,
+}
+
+impl From<Action> for Driver {
+    fn from(act: Action) -> Self {
+        let mut driver = Driver::default();
+        let mut props = act.properties;
+        if !act.payload_string.is_empty() {
+            let p_str = split_property(act.payload_string);
+            props.push(Property {
+                key: p_str.0,
+                value: p_str.1,
+            })
+        }
+        for prop in props {
+            match prop.key.as_str() {
+                "name" => driver.name = prop.value,
+                "alias" => driver.alias.push(prop.value),
+                "class" => driver.class = prop.value,
+                "perms" => driver.perms.push(prop.value),
+                "policy" => driver.policy = prop.value,
+                _ => driver.properties.push(prop),
+            }
+        }
+        driver
+    }
+}
+
+impl From<Driver> for Action {
+    fn from(driver: Driver) -> Self {
+        let mut act = Action::new(ActionKind::Driver);
+        push_property(&mut act.properties, "name", driver.name);
+        for alias in driver.alias {
+            push_property(&mut act.properties, "alias", alias);
+        }
+        push_property(&mut act.properties, "class", driver.class);
+        for perms in driver.perms {
+            push_property(&mut act.properties, "perms", perms);
+        }
+        push_property(&mut act.properties, "policy", driver.policy);
+        act.properties.extend(driver.properties);
+        act
+    }
+}
+
 #[derive(Hash, Eq, PartialEq, Debug, Default, Clone, Deserialize, Serialize, Diff)]
 #[diff(attr(
     #[derive(Debug, PartialEq)]
@@ -464,6 +898,9 @@ pub struct Manifest {
     pub dependencies: Vec<Dependency>,
     pub licenses: Vec<License>,
     pub links: Vec<Link>,
+    pub users: Vec<User>,
+    pub groups: Vec<Group>,
+    pub drivers: Vec<Driver>,
 }
 
 impl Manifest {
@@ -475,6 +912,9 @@ impl Manifest {
             dependencies: Vec::new(),
             licenses: Vec::new(),
             links: Vec::new(),
+            users: Vec::new(),
+            groups: Vec::new(),
+            drivers: Vec::new(),
         }
     }
 
@@ -482,7 +922,129 @@ impl Manifest {
         self.files.push(f);
     }
 
-    fn add_action(&mut self, act: Action) {
+    fn get_attribute_value(&self, key: &str) -> Option<&String> {
+        self.attributes
+            .iter()
+            .find(|attr| attr.key == key)
+            .and_then(|attr| attr.values.first())
+    }
+
+    pub fn human_version(&self) -> Option<String> {
+        self.get_attribute_value("pkg.human-version").cloned()
+    }
+
+    // The version to show to users: pkg.human-version if the package sets one,
+    // otherwise the version of its pkg.fmri.
+    pub fn display_version(&self) -> Option<String> {
+        if let Some(human_version) = self.human_version() {
+            return Some(human_version);
+        }
+        let fmri = self.get_attribute_value("pkg.fmri")?;
+        let (_, version) = fmri.split_once('@')?;
+        Version::parse(version).ok().map(|v| v.to_string())
+    }
+
+    // The info.classification values split into their category path. The
+    // scheme before the colon (org.opensolaris.category.2008) is dropped.
+    pub fn classifications(&self) -> Vec<Vec<String>> {
+        self.attributes
+            .iter()
+            .filter(|attr| attr.key == "info.classification")
+            .flat_map(|attr| attr.values.iter())
+            .map(|value| {
+                let path = value.split_once(':').map_or(value.as_str(), |(_, p)| p);
+                path.split('/')
+                    .map(str::trim)
+                    .filter(|c| !c.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .collect()
+    }
+
+    // All actions of the manifest in their generic form, grouped by kind
+    pub(crate) fn actions(&self) -> Vec<Action> {
+        let mut actions: Vec<Action> = Vec::new();
+        actions.extend(self.attributes.iter().cloned().map(Action::from));
+        actions.extend(self.directories.iter().cloned().map(Action::from));
+        actions.extend(self.files.iter().cloned().map(Action::from));
+        actions.extend(self.dependencies.iter().cloned().map(Action::from));
+        actions.extend(self.licenses.iter().cloned().map(Action::from));
+        actions.extend(self.links.iter().cloned().map(Action::from));
+        actions.extend(self.groups.iter().cloned().map(Action::from));
+        actions.extend(self.users.iter().cloned().map(Action::from));
+        actions.extend(self.drivers.iter().cloned().map(Action::from));
+        actions
+    }
+
+    // Byte representation of the manifest which does not depend on how the
+    // manifest was formatted. Used as the input for signing and verification.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut lines: Vec<String> = self
+            .actions()
+            .into_iter()
+            .map(|mut act| {
+                act.properties
+                    .sort_by(|a, b| a.key.cmp(&b.key).then_with(|| a.value.cmp(&b.value)));
+                act.to_string()
+            })
+            .collect();
+        lines.sort();
+
+        let mut bytes = Vec::new();
+        for line in lines {
+            bytes.extend_from_slice(line.as_bytes());
+            bytes.push(b'\n');
+        }
+        bytes
+    }
+
+    // The manifest in p5m syntax. Actions are grouped by kind and their
+    // properties sorted by key so regenerated manifests can be diffed.
+    pub fn to_manifest_string(&self) -> String {
+        let mut out = String::new();
+        for mut act in self.actions() {
+            act.properties.sort_by(|a, b| a.key.cmp(&b.key));
+            out.push_str(&act.to_string());
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn write_to<W: Write>(&self, w: &mut W) -> Result<()> {
+        w.write_all(self.to_manifest_string().as_bytes())?;
+        Ok(())
+    }
+
+    pub fn dependencies_by_type(&self) -> HashMap<String, Vec<&Dependency>> {
+        let mut by_type: HashMap<String, Vec<&Dependency>> = HashMap::new();
+        for dep in &self.dependencies {
+            by_type
+                .entry(dep.dependency_type.clone())
+                .or_default()
+                .push(dep);
+        }
+        by_type
+    }
+
+    // The actions which apply to an image with the given variants and facets.
+    // Variants are keyed by their full name (variant.arch), facets by their
+    // name without the facet. prefix. Facets not in the map count as enabled.
+    pub fn filter(
+        &self,
+        variants: &HashMap<String, String>,
+        facets: &HashMap<String, bool>,
+    ) -> Manifest {
+        let mut filtered = Manifest::new();
+        for act in self.actions() {
+            if act.matches_variants(variants) && act.matches_facets(facets) {
+                filtered.add_action(act);
+            }
+        }
+        filtered
+    }
+
+    pub(crate) fn add_action(&mut self, act: Action) {
         match act.kind {
             ActionKind::Attr => {
                 self.attributes.push(act.into());
@@ -497,13 +1059,13 @@ impl Manifest {
                 self.dependencies.push(act.into());
             }
             ActionKind::User => {
-                todo!()
+                self.users.push(act.into());
             }
             ActionKind::Group => {
-                todo!()
+                self.groups.push(act.into());
             }
             ActionKind::Driver => {
-                todo!()
+                self.drivers.push(act.into());
             }
             ActionKind::License => {
                 self.licenses.push(act.into());
@@ -546,7 +1108,7 @@ impl Manifest {
                                             act.kind = get_action_kind(action.as_str());
                                         }
                                         Rule::payload => {
-                                            act.payload_string = action.as_str().to_owned();
+                                            act.payload_string = clean_property_value(action.as_str());
                                         }
                                         Rule::property => {
                                             let mut property = Property::default();
@@ -556,9 +1118,7 @@ impl Manifest {
                                                         property.key = prop.as_str().to_owned();
                                                     }
                                                     Rule::property_value => {
-                                                        let str_val: String =  prop.as_str().to_owned();
-                                                        property.value = str_val
-                                                            .replace(['\"', '\\'], "");
+                                                        property.value = clean_property_value(prop.as_str());
                                                     }
                                                     _ => panic!("unexpected rule {:?} inside action expected property_name or property_value", prop.as_rule())
                                                 }
@@ -592,7 +1152,7 @@ impl Manifest {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum ActionKind {
     Attr,
     Dir,
@@ -675,6 +1235,131 @@ fn split_property(property_string: String) -> (String, String) {
     }
 }
 
+// Values containing whitespace, quotes or backslashes have to be written in
+// double quotes with the quotes and backslashes escaped
+fn quote_value(value: &str) -> String {
+    if value.is_empty()
+        || value.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\')
+    {
+        format!(
+            "\"{}\"",
+            value.replace('\\', "\\\\").replace('"', "\\\"")
+        )
+    } else {
+        value.to_owned()
+    }
+}
+
+// Inverse of quote_value for the raw value matched by the parser. A pair of
+// single quotes around the whole value is removed as well, unless there are
+// more single quotes inside, e.g. 'foo' and 'bar'.
+fn clean_property_value(raw: &str) -> String {
+    let value = unquote_value(raw);
+    match value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+        Some(inner) if !inner.contains('\'') => inner.to_owned(),
+        _ => value,
+    }
+}
+
+fn unquote_value(raw: &str) -> String {
+    match raw.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        Some(quoted) => {
+            let mut value = String::with_capacity(quoted.len());
+            let mut chars = quoted.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next()),
+                    c => value.push(c),
+                }
+            }
+            value
+        }
+        None => raw.replace(['\"', '\\'], ""),
+    }
+}
+
+fn push_property(properties: &mut Vec<Property>, key: &str, value: String) {
+    if !value.is_empty() {
+        properties.push(Property {
+            key: key.to_owned(),
+            value,
+        });
+    }
+}
+
+fn facet_properties(facets: HashMap<String, Facet>) -> Vec<Property> {
+    let mut properties: Vec<Property> = facets
+        .into_values()
+        .map(|f| Property {
+            key: format!("facet.{}", f.name),
+            value: f.value,
+        })
+        .collect();
+    properties.sort_by(|a, b| a.key.cmp(&b.key));
+    properties
+}
+
+// Adds the digest of a hash, chash, pkg.content-hash or pkg.hash attribute to
+// the payload of a file or license. Values which are not a valid digest are
+// handed back so they can be kept as plain properties.
+fn add_payload_hash(payload: &mut Payload, prop: Property) -> Option<Property> {
+    let digest = match Digest::from_str(&prop.value) {
+        Ok(digest) => digest,
+        Err(_) => return Some(prop),
+    };
+    // Content hashes are written back as source:algorithm:hash. Values in any
+    // other form, e.g. sha256:<hash> without the source, stay properties.
+    let content_hash = matches!(prop.key.as_str(), "pkg.content-hash" | "pkg.hash");
+    if content_hash && digest.to_string() != prop.value {
+        return Some(prop);
+    }
+    match prop.key.as_str() {
+        "hash" => payload.primary_identifier = digest,
+        // chash is the hash of the gzip compressed payload
+        "chash" => payload.additional_identifiers.push(Digest {
+            source: DigestSource::GzipCompressed,
+            ..digest
+        }),
+        "pkg.hash" => {
+            payload.pkg_hash_identifiers.push(digest.clone());
+            payload.additional_identifiers.push(digest);
+        }
+        _ => payload.additional_identifiers.push(digest),
+    }
+    None
+}
+
+// pkg(5) writes the SHA-1 of the compressed payload as chash, all other
+// digests as pkg.content-hash or pkg.hash with their source.
+fn payload_hash_properties(payload: Payload) -> Vec<Property> {
+    let mut pkg_hashes = payload.pkg_hash_identifiers;
+    payload
+        .additional_identifiers
+        .into_iter()
+        .map(|digest| {
+            if digest.source == DigestSource::GzipCompressed
+                && digest.algorithm == DigestAlgorithm::SHA1
+            {
+                return Property {
+                    key: "chash".to_owned(),
+                    value: digest.hash,
+                };
+            }
+            let key = match pkg_hashes.iter().position(|d| d == &digest) {
+                Some(i) => {
+                    pkg_hashes.remove(i);
+                    "pkg.hash"
+                }
+                None => "pkg.content-hash",
+            };
+            Property {
+                key: key.to_owned(),
+                value: digest.to_string(),
+            }
+        })
+        .collect()
+}
+
 fn string_to_bool(orig: &str) -> Result<bool> {
     match &String::from(orig).trim().to_lowercase()[..] {
         "true" => Ok(true),
@@ -7,6 +7,8 @@ use sha2::Digest as Sha2Digest;
 #[allow(unused_imports)]
 use sha3::Digest as Sha3Digest;
 use std::fmt::Display;
+use std::io::Error as IOError;
+use std::path::Path;
 use std::str::FromStr;
 use std::{convert::TryInto, result::Result as StdResult};
 use diff::Diff;
@@ -27,8 +29,8 @@ pub enum DigestAlgorithm {
     #[default]
     #[strum(serialize = "sha1")]
     SHA1, //Default, sadly
-    #[strum(serialize = "sha256t")]
-    SHA256, //sha256t
+    #[strum(to_string = "sha256", serialize = "sha256t")]
+    SHA256, //sha256, older manifests of ours wrote sha256t
     #[strum(serialize = "sha512t")]
     SHA512, //sha512t
     #[strum(serialize = "sha512t_256")]
@@ -41,6 +43,32 @@ pub enum DigestAlgorithm {
     SHA3512, // Sha3 version of sha512t
 }
 
+impl DigestAlgorithm {
+    pub fn all() -> &'static [DigestAlgorithm] {
+        &[
+            DigestAlgorithm::SHA1,
+            DigestAlgorithm::SHA256,
+            DigestAlgorithm::SHA512,
+            DigestAlgorithm::SHA512Half,
+            DigestAlgorithm::SHA3256,
+            DigestAlgorithm::SHA3512Half,
+            DigestAlgorithm::SHA3512,
+        ]
+    }
+
+    // Length of the hex encoded hash
+    pub fn hex_len(&self) -> usize {
+        match self {
+            DigestAlgorithm::SHA1 => 40,
+            DigestAlgorithm::SHA256
+            | DigestAlgorithm::SHA512Half
+            | DigestAlgorithm::SHA3256
+            | DigestAlgorithm::SHA3512Half => 64,
+            DigestAlgorithm::SHA512 | DigestAlgorithm::SHA3512 => 128,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, StrumDisplay, EnumString, Default, Deserialize, Serialize, Diff)]
 #[diff(attr(
     #[derive(Debug, PartialEq)]
@@ -84,6 +112,19 @@ impl FromStr for Digest {
         }
 
         let parts: Vec<&str> = str.split(':').collect();
+        // hash= and chash= attributes may carry just the algorithm in front,
+        // which kind of payload hash it is depends on the attribute
+        if parts.len() == 2 {
+            return Ok(Digest {
+                hash: String::from(parts[1]),
+                algorithm: parts[0]
+                    .try_into()
+                    .map_err(|_e| DigestError::UnknownAlgorithm {
+                        algorithm: String::from(parts[0]),
+                    })?,
+                source: DigestSource::PrimaryPayloadHash,
+            });
+        }
         if parts.len() < 3 {
             return Err(DigestError::InvalidDigestFormat {
                 digest: String::from(s),
@@ -106,6 +147,9 @@ impl FromStr for Digest {
 impl Digest {
     pub fn from_bytes(b: &[u8], algo: DigestAlgorithm, src: DigestSource) -> Result<Self> {
         let hash = match algo {
+            DigestAlgorithm::SHA1 => {
+                format!("{:x}", sha1::Sha1::digest(b))
+            }
             DigestAlgorithm::SHA256 => {
                 format!("{:x}", sha2::Sha256::digest(b))
             }
@@ -121,11 +165,6 @@ impl Digest {
             DigestAlgorithm::SHA3512 => {
                 format!("{:x}", sha3::Sha3_512::digest(b))
             }
-            x => {
-                return Err(DigestError::UnknownAlgorithm {
-                    algorithm: x.to_string(),
-                })
-            }
         };
 
         Ok(Digest {
@@ -134,6 +173,26 @@ impl Digest {
             hash,
         })
     }
+
+    pub fn from_file<P: AsRef<Path>>(
+        path: P,
+        algo: DigestAlgorithm,
+        src: DigestSource,
+    ) -> Result<Self> {
+        let b = std::fs::read(path)?;
+        Digest::from_bytes(&b, algo, src)
+    }
+}
+
+impl Digest {
+    // The form used for payload hashes in actions. SHA1 stays a bare hash as
+    // that is what older manifests and parsers expect.
+    pub fn to_payload_string(&self) -> String {
+        match self.algorithm {
+            DigestAlgorithm::SHA1 => self.hash.clone(),
+            _ => format!("{}:{}", self.algorithm, self.hash),
+        }
+    }
 }
 
 impl Display for Digest {
@@ -148,4 +207,6 @@ pub enum DigestError {
     UnknownAlgorithm { algorithm: String },
     #[error("digest {digest:?} is not formatted properly: {details:?}")]
     InvalidDigestFormat { digest: String, details: String },
+    #[error("io error: {0}")]
+    IOError(#[from] IOError),
 }
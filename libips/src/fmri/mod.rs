@@ -0,0 +1,408 @@
+//  This Source Code Form is subject to the terms of
+//  the Mozilla Public License, v. 2.0. If a copy of the
+//  MPL was not distributed with this file, You can
+//  obtain one at https://mozilla.org/MPL/2.0/.
+
+// Source https://docs.oracle.com/cd/E23824_01/html/E21796/pkg-5.html
+
+use diff::Diff;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt::Display;
+use std::result::Result as StdResult;
+use std::str::FromStr;
+use thiserror::Error;
+
+type Result<T> = StdResult<T, FmriError>;
+
+#[derive(Debug, Error)]
+pub enum FmriError {
+    #[error("version {version:?} is invalid: {details}")]
+    InvalidVersion { version: String, details: String },
+    #[error("package stem must not be empty")]
+    EmptyStem,
+    #[error("publisher must not be empty, leave it out instead")]
+    EmptyPublisher,
+}
+
+// A package version as found after the '@' of an FMRI:
+// release[,build-release][-branch][:timestamp]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Deserialize, Serialize, Diff)]
+#[diff(attr(
+    #[derive(Debug, PartialEq)]
+))]
+pub struct Version {
+    pub release: String,
+    pub build_release: Option<String>,
+    pub branch: Option<String>,
+    pub timestamp: Option<String>,
+}
+
+impl Version {
+    pub fn parse(s: &str) -> Result<Version> {
+        let invalid = |details: &str| FmriError::InvalidVersion {
+            version: s.to_owned(),
+            details: details.to_owned(),
+        };
+
+        let (rest, timestamp) = match s.split_once(':') {
+            Some((rest, ts)) => (rest, Some(ts.to_owned())),
+            None => (s, None),
+        };
+        let (rest, branch) = match rest.split_once('-') {
+            Some((rest, branch)) => (rest, Some(branch.to_owned())),
+            None => (rest, None),
+        };
+        let (release, build_release) = match rest.split_once(',') {
+            Some((release, build)) => (release, Some(build.to_owned())),
+            None => (rest, None),
+        };
+
+        if !is_dot_sequence(release) {
+            return Err(invalid(
+                "release is not a dot separated sequence of numbers",
+            ));
+        }
+        if let Some(build) = &build_release {
+            if !is_dot_sequence(build) {
+                return Err(invalid(
+                    "build release is not a dot separated sequence of numbers",
+                ));
+            }
+        }
+        if let Some(branch) = &branch {
+            if !is_dot_sequence(branch) {
+                return Err(invalid("branch is not a dot separated sequence of numbers"));
+            }
+        }
+        if let Some(ts) = &timestamp {
+            if ts.is_empty() {
+                return Err(invalid("timestamp is empty"));
+            }
+            if Timestamp::parse(ts).is_none() {
+                return Err(invalid("timestamp is not formatted as YYYYMMDDTHHMMSSZ"));
+            }
+        }
+
+        Ok(Version {
+            release: release.to_owned(),
+            build_release,
+            branch,
+            timestamp,
+        })
+    }
+}
+
+impl Version {
+    // Orders versions the way IPS does: release and branch compare
+    // numerically component by component, then the timestamp. The build
+    // release is not used for ordering. A missing part sorts before a
+    // present one.
+    pub fn cmp_ips(&self, other: &Version) -> Ordering {
+        cmp_dot_sequence(&self.release, &other.release)
+            .then_with(|| cmp_optional_dot_sequence(&self.branch, &other.branch))
+            .then_with(|| self.timestamp().cmp(&other.timestamp()))
+    }
+
+    pub fn timestamp(&self) -> Option<Timestamp> {
+        self.timestamp.as_deref().and_then(Timestamp::parse)
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Fall back to the text so 1.0 and 1.00 are not considered equal
+        self.cmp_ips(other)
+            .then_with(|| self.to_string().cmp(&other.to_string()))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl FromStr for Version {
+    type Err = FmriError;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        Version::parse(s)
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.release)?;
+        if let Some(build) = &self.build_release {
+            write!(f, ",{}", build)?;
+        }
+        if let Some(branch) = &self.branch {
+            write!(f, "-{}", branch)?;
+        }
+        if let Some(ts) = &self.timestamp {
+            write!(f, ":{}", ts)?;
+        }
+        Ok(())
+    }
+}
+
+// The publishing time of a package version, always in UTC: 20200421T195136Z
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct Timestamp {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl Timestamp {
+    pub fn parse(s: &str) -> Option<Timestamp> {
+        let (date, time) = s.strip_suffix('Z')?.split_once('T')?;
+        if date.len() != 8
+            || time.len() != 6
+            || !date.chars().chain(time.chars()).all(|c| c.is_ascii_digit())
+        {
+            return None;
+        }
+
+        let ts = Timestamp {
+            year: date[0..4].parse().ok()?,
+            month: date[4..6].parse().ok()?,
+            day: date[6..8].parse().ok()?,
+            hour: time[0..2].parse().ok()?,
+            minute: time[2..4].parse().ok()?,
+            second: time[4..6].parse().ok()?,
+        };
+        if !(1..=12).contains(&ts.month)
+            || !(1..=31).contains(&ts.day)
+            || ts.hour > 23
+            || ts.minute > 59
+            || ts.second > 60
+        {
+            return None;
+        }
+        Some(ts)
+    }
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Deserialize, Serialize, Diff)]
+#[diff(attr(
+    #[derive(Debug, PartialEq)]
+))]
+pub struct Fmri {
+    pub publisher: Option<String>,
+    pub stem: String,
+    pub version: Option<Version>,
+}
+
+impl Fmri {
+    pub fn new(stem: &str) -> Result<Fmri> {
+        if stem.trim().is_empty() {
+            return Err(FmriError::EmptyStem);
+        }
+        Ok(Fmri {
+            publisher: None,
+            stem: stem.to_owned(),
+            version: None,
+        })
+    }
+
+    pub fn with_version(stem: &str, version: Version) -> Result<Fmri> {
+        Ok(Fmri {
+            version: Some(version),
+            ..Fmri::new(stem)?
+        })
+    }
+
+    pub fn with_publisher(publisher: &str, stem: &str, version: Option<Version>) -> Result<Fmri> {
+        if publisher.trim().is_empty() {
+            return Err(FmriError::EmptyPublisher);
+        }
+        Ok(Fmri {
+            publisher: Some(publisher.to_owned()),
+            version,
+            ..Fmri::new(stem)?
+        })
+    }
+
+    // A copy of this FMRI without publisher, for comparing package identity
+    // across publishers.
+    pub fn without_publisher(&self) -> Fmri {
+        Fmri {
+            publisher: None,
+            ..self.clone()
+        }
+    }
+
+    // Same package and release, build release and branch. The timestamp is
+    // ignored so a republish of the same version is not seen as an upgrade.
+    pub fn same_release(&self, other: &Fmri) -> bool {
+        if self.stem != other.stem {
+            return false;
+        }
+        match (&self.version, &other.version) {
+            (Some(a), Some(b)) => {
+                a.release == b.release && a.build_release == b.build_release && a.branch == b.branch
+            }
+            (a, b) => a.is_none() && b.is_none(),
+        }
+    }
+
+    // Compares the versions only, an FMRI without version is older than any
+    // versioned one.
+    pub fn is_newer_than(&self, other: &Fmri) -> bool {
+        self.version > other.version
+    }
+
+    // Accepts pkg://publisher/stem@version, pkg:/stem@version and plain
+    // stem@version. The version is optional in all forms.
+    pub fn parse(s: &str) -> Result<Fmri> {
+        let (publisher, rest) = if let Some(rest) = s.strip_prefix("pkg://") {
+            match rest.split_once('/') {
+                Some((publisher, rest)) => (Some(publisher), rest),
+                None => return Err(FmriError::EmptyStem),
+            }
+        } else if let Some(rest) = s.strip_prefix("pkg:/") {
+            (None, rest)
+        } else {
+            (None, s)
+        };
+
+        let (stem, version) = match rest.split_once('@') {
+            Some((stem, version)) => (stem, Some(Version::parse(version)?)),
+            None => (rest, None),
+        };
+
+        match publisher {
+            Some(publisher) => Fmri::with_publisher(publisher, stem, version),
+            None => Ok(Fmri {
+                version,
+                ..Fmri::new(stem)?
+            }),
+        }
+    }
+
+    // Matches FMRI patterns as given on the command line. * and ? glob in the
+    // publisher, stem and version. A pattern without pkg:/ or pkg:// also
+    // matches the trailing components of the stem, so nginx matches
+    // web/server/nginx. A version pattern matches on whole components, 1.18
+    // matches 1.18.0 but not 1.180.
+    pub fn matches_pattern(&self, pattern: &str) -> bool {
+        let (publisher, anchored, rest) = if let Some(rest) = pattern.strip_prefix("pkg://") {
+            match rest.split_once('/') {
+                Some((publisher, rest)) => (Some(publisher), true, rest),
+                None => return false,
+            }
+        } else if let Some(rest) = pattern.strip_prefix("pkg:/") {
+            (None, true, rest)
+        } else {
+            (None, false, pattern)
+        };
+        let (stem, version) = match rest.split_once('@') {
+            Some((stem, version)) => (stem, Some(version)),
+            None => (rest, None),
+        };
+
+        if let Some(publisher) = publisher {
+            match &self.publisher {
+                Some(own) if glob_matches(publisher, "", own) => (),
+                None if publisher == "*" => (),
+                _ => return false,
+            }
+        }
+
+        let stem_prefix = if anchored { "" } else { "(?:.*/)?" };
+        if !glob_matches(stem, stem_prefix, &self.stem) {
+            return false;
+        }
+
+        match (version, &self.version) {
+            (None, _) => true,
+            (Some(pattern), Some(version)) => glob_matches_prefix(pattern, &version.to_string()),
+            (Some(_), None) => false,
+        }
+    }
+
+    // Catalogs key their entries by "stem@version" without scheme or publisher.
+    pub fn from_catalog_key(key: &str) -> Result<Fmri> {
+        match key.split_once('@') {
+            Some((stem, version)) => Fmri::with_version(stem, Version::parse(version)?),
+            None => Fmri::new(key),
+        }
+    }
+}
+
+impl FromStr for Fmri {
+    type Err = FmriError;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        Fmri::parse(s)
+    }
+}
+
+impl Display for Fmri {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.publisher {
+            Some(publisher) => write!(f, "pkg://{}/{}", publisher, self.stem)?,
+            None => write!(f, "pkg:/{}", self.stem)?,
+        }
+        if let Some(version) = &self.version {
+            write!(f, "@{}", version)?;
+        }
+        Ok(())
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    regex::escape(glob).replace(r"\*", ".*").replace(r"\?", ".")
+}
+
+// prefix is a regex put in front of the translated glob
+fn glob_matches(glob: &str, prefix: &str, s: &str) -> bool {
+    Regex::new(&format!("^{}{}$", prefix, glob_to_regex(glob)))
+        .map(|re| re.is_match(s))
+        .unwrap_or(false)
+}
+
+// Matches the glob against whole leading components of a version string
+fn glob_matches_prefix(glob: &str, s: &str) -> bool {
+    Regex::new(&format!("^{}(?:[.,:-].*)?$", glob_to_regex(glob)))
+        .map(|re| re.is_match(s))
+        .unwrap_or(false)
+}
+
+fn cmp_dot_sequence(a: &str, b: &str) -> Ordering {
+    let components = |s: &str| -> Vec<u64> {
+        s.split('.')
+            .map(|part| part.parse().unwrap_or(u64::MAX))
+            .collect()
+    };
+    components(a).cmp(&components(b))
+}
+
+fn cmp_optional_dot_sequence(a: &Option<String>, b: &Option<String>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => cmp_dot_sequence(a, b),
+        (a, b) => a.is_some().cmp(&b.is_some()),
+    }
+}
+
+fn is_dot_sequence(s: &str) -> bool {
+    !s.is_empty()
+        && s.split('.')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+}
@@ -0,0 +1,38 @@
+use super::{ImageError, Result};
+use std::fmt::Debug;
+use std::process::Command;
+
+// Abstraction over the service management facility so actuators can be
+// triggered against SMF on a live system or recorded in tests.
+pub trait ServiceManager: Debug + Send + Sync {
+    fn restart(&self, fmri: &str) -> Result<()>;
+    fn refresh(&self, fmri: &str) -> Result<()>;
+}
+
+// Default ServiceManager which shells out to svcadm(8).
+#[derive(Debug, Default, Clone)]
+pub struct Svcadm;
+
+impl Svcadm {
+    fn run(&self, subcommand: &str, fmri: &str) -> Result<()> {
+        let status = Command::new("svcadm").args([subcommand, fmri]).status()?;
+        if !status.success() {
+            return Err(ImageError::ServiceActionFailed {
+                action: subcommand.to_owned(),
+                fmri: fmri.to_owned(),
+                code: status.code().unwrap_or(-1),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl ServiceManager for Svcadm {
+    fn restart(&self, fmri: &str) -> Result<()> {
+        self.run("restart", fmri)
+    }
+
+    fn refresh(&self, fmri: &str) -> Result<()> {
+        self.run("refresh", fmri)
+    }
+}
@@ -0,0 +1,76 @@
+use super::{Image, Result};
+use crate::fmri::Fmri;
+use serde::{Deserialize, Serialize};
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum HistoryOutcome {
+    Succeeded,
+    Failed(String),
+}
+
+// One operation on the image, e.g. an install or uninstall
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    pub operation: String,
+    // Seconds since the unix epoch
+    pub timestamp: u64,
+    pub fmris: Vec<Fmri>,
+    pub outcome: HistoryOutcome,
+}
+
+impl HistoryEntry {
+    pub fn new(operation: &str, fmris: Vec<Fmri>, outcome: HistoryOutcome) -> HistoryEntry {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        HistoryEntry {
+            operation: operation.to_owned(),
+            timestamp,
+            fmris,
+            outcome,
+        }
+    }
+}
+
+impl Image {
+    fn history_path(&self) -> PathBuf {
+        self.path.join("var/pkg/history/history.jsonl")
+    }
+
+    // Append an entry to the history log of the image, one json object per line
+    pub fn record_history(&self, entry: &HistoryEntry) -> Result<()> {
+        let path = self.history_path();
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir)?;
+        }
+        let mut f = OpenOptions::new().create(true).append(true).open(path)?;
+        serde_json::to_writer(&mut f, entry)?;
+        writeln!(f)?;
+        Ok(())
+    }
+
+    // The recorded operations newest first, at most limit entries if given
+    pub fn history(&self, limit: Option<usize>) -> Result<Vec<HistoryEntry>> {
+        let path = self.history_path();
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        let mut entries = vec![];
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                entries.push(serde_json::from_str(&line)?);
+            }
+        }
+        entries.reverse();
+        if let Some(limit) = limit {
+            entries.truncate(limit);
+        }
+        Ok(entries)
+    }
+}
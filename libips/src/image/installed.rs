@@ -0,0 +1,136 @@
+use super::{ImageError, Result};
+use crate::actions::Manifest;
+use crate::fmri::Fmri;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct InstalledPackage {
+    pub fmri: Fmri,
+    pub manifest: Manifest,
+    // Installed on request of the user, false if pulled in as a dependency.
+    // Packages recorded before this was tracked count as manual.
+    #[serde(default = "manual_default")]
+    pub manual: bool,
+}
+
+fn manual_default() -> bool {
+    true
+}
+
+// Storage of the packages installed into an image. Only one version of a
+// package stem can be installed at a time, so entries are keyed by stem.
+pub trait InstalledDb: Debug + Send + Sync {
+    fn add(&self, fmri: &Fmri, manifest: &Manifest, manual: bool) -> Result<()>;
+    fn remove(&self, stem: &str) -> Result<()>;
+    fn get(&self, stem: &str) -> Result<Option<InstalledPackage>>;
+    fn list(&self) -> Result<Vec<Fmri>>;
+}
+
+type InstalledMap = BTreeMap<String, InstalledPackage>;
+
+// Default InstalledDb keeping the installed packages in a json file next to
+// the image configuration.
+#[derive(Debug)]
+pub struct FileInstalledDb {
+    path: PathBuf,
+}
+
+impl FileInstalledDb {
+    pub fn new<P: Into<PathBuf>>(image_path: P) -> FileInstalledDb {
+        FileInstalledDb {
+            path: image_path.into().join("pkg6.installed.json"),
+        }
+    }
+
+    fn read(&self) -> Result<InstalledMap> {
+        if !self.path.exists() {
+            return Ok(InstalledMap::new());
+        }
+        let mut f = File::open(&self.path)?;
+        Ok(serde_json::from_reader(&mut f)?)
+    }
+
+    fn write(&self, packages: &InstalledMap) -> Result<()> {
+        let mut f = File::create(&self.path)?;
+        serde_json::to_writer(&mut f, packages)?;
+        Ok(())
+    }
+}
+
+impl InstalledDb for FileInstalledDb {
+    fn add(&self, fmri: &Fmri, manifest: &Manifest, manual: bool) -> Result<()> {
+        let mut packages = self.read()?;
+        packages.insert(
+            fmri.stem.clone(),
+            InstalledPackage {
+                fmri: fmri.clone(),
+                manifest: manifest.clone(),
+                manual,
+            },
+        );
+        self.write(&packages)
+    }
+
+    fn remove(&self, stem: &str) -> Result<()> {
+        let mut packages = self.read()?;
+        if packages.remove(stem).is_none() {
+            return Err(ImageError::PackageNotInstalled(stem.to_owned()));
+        }
+        self.write(&packages)
+    }
+
+    fn get(&self, stem: &str) -> Result<Option<InstalledPackage>> {
+        Ok(self.read()?.remove(stem))
+    }
+
+    fn list(&self) -> Result<Vec<Fmri>> {
+        Ok(self.read()?.into_values().map(|p| p.fmri).collect())
+    }
+}
+
+// InstalledDb which never touches the disk. Mostly useful for tests and for
+// embedding libips where the image state is managed elsewhere.
+#[derive(Debug, Default)]
+pub struct MemoryInstalledDb {
+    packages: Mutex<InstalledMap>,
+}
+
+impl InstalledDb for MemoryInstalledDb {
+    fn add(&self, fmri: &Fmri, manifest: &Manifest, manual: bool) -> Result<()> {
+        self.packages.lock().unwrap().insert(
+            fmri.stem.clone(),
+            InstalledPackage {
+                fmri: fmri.clone(),
+                manifest: manifest.clone(),
+                manual,
+            },
+        );
+        Ok(())
+    }
+
+    fn remove(&self, stem: &str) -> Result<()> {
+        match self.packages.lock().unwrap().remove(stem) {
+            Some(_) => Ok(()),
+            None => Err(ImageError::PackageNotInstalled(stem.to_owned())),
+        }
+    }
+
+    fn get(&self, stem: &str) -> Result<Option<InstalledPackage>> {
+        Ok(self.packages.lock().unwrap().get(stem).cloned())
+    }
+
+    fn list(&self) -> Result<Vec<Fmri>> {
+        Ok(self
+            .packages
+            .lock()
+            .unwrap()
+            .values()
+            .map(|p| p.fmri.clone())
+            .collect())
+    }
+}
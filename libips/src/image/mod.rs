@@ -1,9 +1,24 @@
+mod actuators;
+mod history;
+mod installed;
 mod properties;
+mod uninstall;
+mod verify;
 
-use std::collections::HashMap;
+pub use actuators::{ServiceManager, Svcadm};
+pub use history::{HistoryEntry, HistoryOutcome};
+pub use installed::{FileInstalledDb, InstalledDb, InstalledPackage, MemoryInstalledDb};
+pub use uninstall::UninstallReport;
+pub use verify::{VerifyIssue, VerifyProblem};
+
+use crate::actions::File as FileAction;
+use crate::actions::Manifest;
+use crate::fmri::Fmri;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use properties::*;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -14,6 +29,14 @@ pub enum ImageError {
     IO(#[from] std::io::Error),
     #[error(transparent)]
     Json(#[from] serde_json::Error),
+    #[error("package {0} is not installed")]
+    PackageNotInstalled(String),
+    #[error("svcadm {action} {fmri} returned exit code: {code}")]
+    ServiceActionFailed {
+        action: String,
+        fmri: String,
+        code: i32,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, ImageError>;
@@ -24,7 +47,17 @@ pub struct Image {
     props: Vec<ImageProperty>,
     version: i32,
     variants: HashMap<String, String>,
+    #[serde(default)]
+    facets: HashMap<String, bool>,
     mediators: HashMap<String, String>,
+    #[serde(skip)]
+    installed_db: Option<Arc<dyn InstalledDb>>,
+    #[serde(skip)]
+    service_manager: Option<Arc<dyn ServiceManager>>,
+    // Number of configuration writes, lets tests check that changes are batched
+    #[cfg(test)]
+    #[serde(skip)]
+    pub(crate) saves: Arc<std::sync::atomic::AtomicUsize>,
 }
 
 impl Image {
@@ -33,8 +66,34 @@ impl Image {
             path: path.into(),
             version: 5,
             variants: HashMap::new(),
+            facets: HashMap::new(),
             mediators: HashMap::new(),
             props: vec![],
+            installed_db: None,
+            service_manager: None,
+            #[cfg(test)]
+            saves: Default::default(),
+        }
+    }
+
+    // Create an image whose installed packages are kept in the given database
+    // instead of the default file next to the image configuration.
+    pub fn with_installed_db<P: Into<PathBuf>>(path: P, db: Arc<dyn InstalledDb>) -> Image {
+        Image {
+            installed_db: Some(db),
+            ..Image::new(path)
+        }
+    }
+
+    // Create an image which triggers actuators through the given service
+    // manager instead of svcadm(8).
+    pub fn with_service_manager<P: Into<PathBuf>>(
+        path: P,
+        svc: Arc<dyn ServiceManager>,
+    ) -> Image {
+        Image {
+            service_manager: Some(svc),
+            ..Image::new(path)
         }
     }
 
@@ -48,6 +107,85 @@ impl Image {
         Ok(serde_json::from_reader(&mut f)?)
     }
 
+    pub fn save(&self) -> Result<()> {
+        let props_path = self.path.join("pkg6.image.json");
+        let mut f = File::create(props_path)?;
+        serde_json::to_writer_pretty(&mut f, self)?;
+        #[cfg(test)]
+        self.saves.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub fn installed_db(&self) -> Arc<dyn InstalledDb> {
+        match &self.installed_db {
+            Some(db) => db.clone(),
+            None => Arc::new(FileInstalledDb::new(&self.path)),
+        }
+    }
+
+    pub fn service_manager(&self) -> Arc<dyn ServiceManager> {
+        match &self.service_manager {
+            Some(svc) => svc.clone(),
+            None => Arc::new(Svcadm),
+        }
+    }
+
+    // The manifest of the installed package. Publisher and version of the
+    // FMRI are only compared when given.
+    pub fn installed_manifest(&self, fmri: &Fmri) -> Result<Option<Manifest>> {
+        let installed = match self.installed_db().get(&fmri.stem)? {
+            Some(installed) => installed,
+            None => return Ok(None),
+        };
+        if fmri.publisher.is_some() && fmri.publisher != installed.fmri.publisher {
+            return Ok(None);
+        }
+        if fmri.version.is_some() && fmri.version != installed.fmri.version {
+            return Ok(None);
+        }
+        Ok(Some(installed.manifest))
+    }
+
+    // The FMRI the package stem is installed at, if it is installed
+    pub fn installed_version(&self, stem: &str) -> Result<Option<Fmri>> {
+        Ok(self.installed_db().get(stem)?.map(|installed| installed.fmri))
+    }
+
+    pub fn installed_packages(&self) -> Result<Vec<Fmri>> {
+        self.installed_db().list()
+    }
+
+    // Whether the user asked for the package or it was only installed as a
+    // dependency. Packages which are not installed are not manual.
+    pub fn is_manually_installed(&self, stem: &str) -> Result<bool> {
+        Ok(self
+            .installed_db()
+            .get(stem)?
+            .is_some_and(|installed| installed.manual))
+    }
+
+    pub fn variants(&self) -> &HashMap<String, String> {
+        &self.variants
+    }
+
+    pub fn facets(&self) -> &HashMap<String, bool> {
+        &self.facets
+    }
+
+    // Set all given variants at once and persist the image configuration a
+    // single time rather than once per variant.
+    pub fn set_variants(&mut self, variants: HashMap<String, String>) -> Result<()> {
+        self.variants.extend(variants);
+        self.save()
+    }
+
+    // Set all given facets at once and persist the image configuration a
+    // single time rather than once per facet.
+    pub fn set_facets(&mut self, facets: HashMap<String, bool>) -> Result<()> {
+        self.facets.extend(facets);
+        self.save()
+    }
+
     pub fn open_default<P: AsRef<Path>>(path: P) -> Image {
         if let Ok(img) = Image::open(path.as_ref()) {
             img
@@ -55,4 +193,38 @@ impl Image {
             Image::new(path.as_ref())
         }
     }
-}
\ No newline at end of file
+
+    // Trigger the restart_fmri and refresh_fmri actuators of the changed file
+    // actions. Each service is acted upon only once, in the order it was first
+    // seen, no matter how many files name it.
+    pub fn run_actuators(&self, changed: &[FileAction]) -> Result<()> {
+        let svc = self.service_manager();
+        let mut restart: Vec<&str> = Vec::new();
+        let mut refresh: Vec<&str> = Vec::new();
+        let mut seen_restart = HashSet::new();
+        let mut seen_refresh = HashSet::new();
+
+        for f in changed {
+            for fmri in &f.actuators.restart_fmri {
+                if seen_restart.insert(fmri.as_str()) {
+                    restart.push(fmri);
+                }
+            }
+            for fmri in &f.actuators.refresh_fmri {
+                if seen_refresh.insert(fmri.as_str()) {
+                    refresh.push(fmri);
+                }
+            }
+        }
+
+        for fmri in refresh {
+            svc.refresh(fmri)?;
+        }
+
+        for fmri in restart {
+            svc.restart(fmri)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use super::{HistoryEntry, HistoryOutcome, Image, ImageError, Result};
+use crate::actions::Manifest;
+use crate::fmri::Fmri;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs::{read_dir, remove_dir, remove_file, symlink_metadata};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+// What uninstalling a package removed, or would remove in a dry run
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct UninstallReport {
+    pub fmri: Fmri,
+    // Files, links and directories removed from the image
+    pub removed: Vec<String>,
+    // Files marked preserve which were left in place as force was not given
+    pub preserved: Vec<String>,
+}
+
+impl Image {
+    // Remove the files, links and directories of the installed packages and
+    // drop them from the installed database. Packages are removed before the
+    // packages they depend on. Directories are only removed when empty and
+    // preserve files are kept unless force is set. A dry run only reports.
+    pub fn uninstall(
+        &self,
+        stems: &[String],
+        force: bool,
+        dry_run: bool,
+    ) -> Result<Vec<UninstallReport>> {
+        let db = self.installed_db();
+        let mut packages = vec![];
+        for stem in stems {
+            match db.get(stem)? {
+                Some(installed) => packages.push(installed),
+                None => return Err(ImageError::PackageNotInstalled(stem.to_owned())),
+            }
+        }
+
+        let mut reports = vec![];
+        while !packages.is_empty() {
+            let next = (0..packages.len())
+                .find(|&i| {
+                    !packages
+                        .iter()
+                        .any(|other| depends_on(&other.manifest, &packages[i].fmri.stem))
+                })
+                .unwrap_or(0);
+            let installed = packages.remove(next);
+            let report = self.remove_package(&installed.fmri, &installed.manifest, force, dry_run)?;
+            if !dry_run {
+                db.remove(&installed.fmri.stem)?;
+                self.record_history(&HistoryEntry::new(
+                    "uninstall",
+                    vec![installed.fmri.clone()],
+                    HistoryOutcome::Succeeded,
+                ))?;
+            }
+            reports.push(report);
+        }
+        Ok(reports)
+    }
+
+    fn remove_package(
+        &self,
+        fmri: &Fmri,
+        manifest: &Manifest,
+        force: bool,
+        dry_run: bool,
+    ) -> Result<UninstallReport> {
+        let mut report = UninstallReport {
+            fmri: fmri.clone(),
+            removed: vec![],
+            preserved: vec![],
+        };
+        let mut removed: HashSet<PathBuf> = HashSet::new();
+
+        let files = manifest.files.iter().map(|f| (&f.path, f.preserve));
+        let links = manifest.links.iter().map(|l| (&l.path, false));
+        for (path, preserve) in files.chain(links) {
+            let full_path = self.path.join(path);
+            if symlink_metadata(&full_path).is_err() {
+                continue;
+            }
+            if preserve && !force {
+                report.preserved.push(path.clone());
+                continue;
+            }
+            if !dry_run {
+                remove_file(&full_path)?;
+            }
+            removed.insert(full_path);
+            report.removed.push(path.clone());
+        }
+
+        // Deepest first so parents are empty by the time they are checked
+        let mut dirs: Vec<&String> = manifest.directories.iter().map(|d| &d.path).collect();
+        dirs.sort_by_key(|path| std::cmp::Reverse(Path::new(path).components().count()));
+        for path in dirs {
+            let full_path = self.path.join(path);
+            if !is_empty_after(&full_path, &removed)? {
+                continue;
+            }
+            if !dry_run {
+                remove_dir(&full_path)?;
+            }
+            removed.insert(full_path);
+            report.removed.push(path.clone());
+        }
+
+        Ok(report)
+    }
+}
+
+fn depends_on(manifest: &Manifest, stem: &str) -> bool {
+    manifest
+        .dependencies
+        .iter()
+        .filter_map(|dep| Fmri::parse(&dep.fmri).ok())
+        .any(|fmri| fmri.stem == stem)
+}
+
+// Whether the directory exists and holds nothing but already removed entries
+fn is_empty_after(dir: &Path, removed: &HashSet<PathBuf>) -> Result<bool> {
+    let entries = match read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        if !removed.contains(&entry?.path()) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
@@ -0,0 +1,225 @@
+use super::{Image, Result};
+use crate::actions::{Dir, File, Link};
+use crate::fmri::Fmri;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs::{read_to_string, Metadata};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum VerifyProblem {
+    Missing,
+    Mode { expected: u32, actual: u32 },
+    Owner { expected: u32, actual: u32 },
+    Group { expected: u32, actual: u32 },
+    // The owner or group name is in neither the image nor the host database
+    UnknownOwner(String),
+    UnknownGroup(String),
+    Content(String),
+    DanglingLink { target: String },
+}
+
+// A difference between an installed action and the state on disk
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerifyIssue {
+    pub fmri: Fmri,
+    pub path: String,
+    pub problem: VerifyProblem,
+}
+
+impl Display for VerifyProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifyProblem::Missing => write!(f, "missing"),
+            VerifyProblem::Mode { expected, actual } => {
+                write!(f, "mode is {:04o}, expected {:04o}", actual, expected)
+            }
+            VerifyProblem::Owner { expected, actual } => {
+                write!(f, "owner is {}, expected {}", actual, expected)
+            }
+            VerifyProblem::Group { expected, actual } => {
+                write!(f, "group is {}, expected {}", actual, expected)
+            }
+            VerifyProblem::UnknownOwner(name) => {
+                write!(f, "owner {} is unknown, cannot verify it", name)
+            }
+            VerifyProblem::UnknownGroup(name) => {
+                write!(f, "group {} is unknown, cannot verify it", name)
+            }
+            VerifyProblem::Content(details) => write!(f, "content differs: {}", details),
+            VerifyProblem::DanglingLink { target } => {
+                write!(f, "link target {} does not exist", target)
+            }
+        }
+    }
+}
+
+impl Display for VerifyIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}: {}", self.fmri, self.path, self.problem)
+    }
+}
+
+// Numeric ids of the user and group names known to the image
+struct IdMap {
+    users: HashMap<String, u32>,
+    groups: HashMap<String, u32>,
+}
+
+impl IdMap {
+    // Reads etc/passwd and etc/group of the image, or those of the host if
+    // the image has none.
+    fn load(image_root: &Path) -> IdMap {
+        let read = |file: &str| {
+            read_to_string(image_root.join("etc").join(file))
+                .or_else(|_| read_to_string(Path::new("/etc").join(file)))
+                .map(|content| parse_id_file(&content))
+                .unwrap_or_default()
+        };
+        IdMap {
+            users: read("passwd"),
+            groups: read("group"),
+        }
+    }
+}
+
+// passwd and group files both have the name first and the id third
+fn parse_id_file(content: &str) -> HashMap<String, u32> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            let id = fields.get(2)?.parse().ok()?;
+            Some((fields[0].to_owned(), id))
+        })
+        .collect()
+}
+
+impl Image {
+    // Compare the files, directories and links of all installed packages with
+    // what is on disk. Owner and group names are resolved with the passwd and
+    // group files of the image.
+    pub fn verify(&self) -> Result<Vec<VerifyIssue>> {
+        let db = self.installed_db();
+        let ids = IdMap::load(&self.path);
+        let mut issues = Vec::new();
+        for fmri in db.list()? {
+            let manifest = match db.get(&fmri.stem)? {
+                Some(installed) => installed.manifest,
+                None => continue,
+            };
+            let mut report = |path: &str, problem: VerifyProblem| {
+                issues.push(VerifyIssue {
+                    fmri: fmri.clone(),
+                    path: path.to_owned(),
+                    problem,
+                })
+            };
+
+            for dir in &manifest.directories {
+                for problem in self.verify_dir(dir, &ids) {
+                    report(&dir.path, problem);
+                }
+            }
+            for file in &manifest.files {
+                for problem in self.verify_file(file, &ids) {
+                    report(&file.path, problem);
+                }
+            }
+            for link in &manifest.links {
+                if let Some(problem) = self.verify_link(link) {
+                    report(&link.path, problem);
+                }
+            }
+        }
+        Ok(issues)
+    }
+
+    fn verify_dir(&self, dir: &Dir, ids: &IdMap) -> Vec<VerifyProblem> {
+        match self.path.join(&dir.path).metadata() {
+            Ok(meta) => verify_attributes(&meta, &dir.mode, &dir.owner, &dir.group, ids),
+            Err(_) => vec![VerifyProblem::Missing],
+        }
+    }
+
+    fn verify_file(&self, file: &File, ids: &IdMap) -> Vec<VerifyProblem> {
+        let path = self.path.join(&file.path);
+        let meta = match path.metadata() {
+            Ok(meta) => meta,
+            Err(_) => return vec![VerifyProblem::Missing],
+        };
+
+        let mut problems = verify_attributes(&meta, &file.mode, &file.owner, &file.group, ids);
+        // Editable files are expected to change
+        if !file.preserve {
+            if let Some(payload) = &file.payload {
+                if let Err(e) = payload.verify_file(&path) {
+                    problems.push(VerifyProblem::Content(e.to_string()));
+                }
+            }
+        }
+        problems
+    }
+
+    fn verify_link(&self, link: &Link) -> Option<VerifyProblem> {
+        let path = self.path.join(&link.path);
+        if path.symlink_metadata().is_err() {
+            return Some(VerifyProblem::Missing);
+        }
+        let parent = path.parent().unwrap_or_else(|| Path::new("/"));
+        let target = if link.target.starts_with('/') {
+            self.path.join(link.target.trim_start_matches('/'))
+        } else {
+            parent.join(&link.target)
+        };
+        if target.exists() {
+            None
+        } else {
+            Some(VerifyProblem::DanglingLink {
+                target: link.target.clone(),
+            })
+        }
+    }
+}
+
+fn verify_attributes(
+    meta: &Metadata,
+    mode: &str,
+    owner: &str,
+    group: &str,
+    ids: &IdMap,
+) -> Vec<VerifyProblem> {
+    let mut problems = Vec::new();
+    if let Ok(expected) = u32::from_str_radix(mode, 8) {
+        let actual = meta.permissions().mode() & 0o7777;
+        if actual != expected {
+            problems.push(VerifyProblem::Mode { expected, actual });
+        }
+    }
+    if !owner.is_empty() {
+        match owner.parse().ok().or_else(|| ids.users.get(owner).copied()) {
+            Some(expected) if meta.uid() != expected => problems.push(VerifyProblem::Owner {
+                expected,
+                actual: meta.uid(),
+            }),
+            Some(_) => {}
+            None => problems.push(VerifyProblem::UnknownOwner(owner.to_owned())),
+        }
+    }
+    if !group.is_empty() {
+        match group
+            .parse()
+            .ok()
+            .or_else(|| ids.groups.get(group).copied())
+        {
+            Some(expected) if meta.gid() != expected => problems.push(VerifyProblem::Group {
+                expected,
+                actual: meta.gid(),
+            }),
+            Some(_) => {}
+            None => problems.push(VerifyProblem::UnknownGroup(group.to_owned())),
+        }
+    }
+    problems
+}
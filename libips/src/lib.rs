@@ -6,17 +6,27 @@
 #[allow(clippy::result_large_err)]
 pub mod actions;
 pub mod digest;
+pub mod fmri;
 pub mod payload;
 pub mod image;
+#[allow(clippy::result_large_err)]
+pub mod transformer;
 
 #[cfg(test)]
 mod tests {
 
-    use crate::actions::Attr;
-    use crate::actions::{Dependency, Dir, Facet, File, Link, Manifest, Property};
+    use crate::actions::{Action, Actuators, Attr};
+    use crate::actions::{Dependency, Dir, Driver, Facet, FacetedAction, File, Group, License, Link, Manifest, Property, User};
     use crate::digest::{Digest, DigestAlgorithm, DigestSource};
-    use crate::payload::Payload;
-    use std::collections::HashMap;
+    use crate::fmri::{Fmri, Timestamp, Version};
+    use crate::image::{
+        HistoryEntry, HistoryOutcome, Image, MemoryInstalledDb, ServiceManager, VerifyProblem,
+    };
+    use crate::payload::{Payload, PayloadError};
+    use crate::transformer::{apply_all, reconcile, Transform, TransformError};
+    use std::cmp::Ordering;
+    use std::str::FromStr;
+    use std::sync::{Arc, Mutex};
 
     use maplit::hashmap;
 
@@ -40,107 +50,102 @@ set name=variant.arch value=i386 optional=testing optionalWithString=\"test ing\
 set name=info.source-url value=\"http://www.pgpool.net/download.php?f=pgpool-II-3.3.1.tar.gz\"
 set name=pkg.summary value=\"'XZ Utils - loss-less file compression application and library.'\"");
 
-        let mut optional_hash = HashMap::new();
-        optional_hash.insert(
-            String::from("optional"),
+        let optional_properties = vec![
             Property {
                 key: String::from("optional"),
                 value: String::from("testing"),
             },
-        );
-        optional_hash.insert(
-            String::from("optionalWithString"),
             Property {
                 key: String::from("optionalWithString"),
                 value: String::from("test ing"),
             },
-        );
+        ];
 
         let test_results = vec![
             Attr{
                 key: String::from("pkg.fmri"),
                 values: vec![String::from("pkg://openindiana.org/web/server/nginx@1.18.0,5.11-2020.0.1.0:20200421T195136Z")],
-                properties: HashMap::new(),
+                properties: vec![],
             },
             Attr{
                 key: String::from("com.oracle.info.name"),
                 values: vec![String::from("nginx"), String::from("test")],
-                properties: HashMap::new(),
+                properties: vec![],
             },
             Attr{
                 key: String::from("userland.info.git-remote"),
                 values: vec![String::from("git://github.com/OpenIndiana/oi-userland.git")],
-                properties: HashMap::new(),
+                properties: vec![],
             },
             Attr{
                 key: String::from("userland.info.git-branch"),
                 values: vec![String::from("HEAD")],
-                properties: HashMap::new(),
+                properties: vec![],
             },
             Attr{
                 key: String::from("userland.info.git-rev"),
                 values: vec![String::from("1665491ba61bd494bf73e2916cd2250f3024260e")],
-                properties: HashMap::new(),
+                properties: vec![],
             },
             Attr{
                 key: String::from("pkg.summary"),
                 values: vec![String::from("Nginx Webserver")],
-                properties: HashMap::new(),
+                properties: vec![],
             },
             Attr{
                 key: String::from("info.classification"),
                 values: vec![String::from("org.opensolaris.category.2008:Web Services/Application and Web Servers")],
-                properties: HashMap::new(),
+                properties: vec![],
             },
             Attr{
                 key: String::from("info.upstream-url"),
                 values: vec![String::from("http://nginx.net/")],
-                properties: HashMap::new(),
+                properties: vec![],
             },
             Attr{
                 key: String::from("info.source-url"),
                 values: vec![String::from("http://nginx.org/download/nginx-1.18.0.tar.gz")],
-                properties: HashMap::new(),
+                properties: vec![],
             },
             Attr{
                 key: String::from("org.opensolaris.consolidation"),
                 values: vec![String::from("userland")],
-                properties: HashMap::new(),
+                properties: vec![],
             },
             Attr{
                 key: String::from("com.oracle.info.version"),
                 values: vec![String::from("1.18.0")],
-                properties: HashMap::new(),
+                properties: vec![],
             },
             Attr{
                 key: String::from("pkg.summary"),
                 values: vec![String::from("provided mouse accessibility enhancements")],
-                properties: HashMap::new(),
+                properties: vec![],
             },
             Attr{
                 key: String::from("info.upstream"),
                 values: vec![String::from("X.Org Foundation")],
-                properties: HashMap::new(),
+                properties: vec![],
             },
             Attr{
                 key: String::from("pkg.description"),
                 values: vec![String::from("Latvian language support's extra files")],
-                properties: HashMap::new(),
+                properties: vec![],
             },
             Attr{
                 key: String::from("variant.arch"),
                 values: vec![String::from("i386")],
-                properties: optional_hash,
+                properties: optional_properties,
             },
             Attr{
                 key: String::from("info.source-url"),
                 values: vec![String::from("http://www.pgpool.net/download.php?f=pgpool-II-3.3.1.tar.gz")],
-                properties: HashMap::new(),
+                properties: vec![],
             },
             Attr{
                 key: String::from("pkg.summary"),
-                values: vec![String::from("'XZ Utils - loss-less file compression application and library.'")], //TODO knock out the single quotes
-                properties: HashMap::new(),
+                values: vec![String::from("XZ Utils - loss-less file compression application and library.")],
+                properties: vec![],
             }
         ];
 
@@ -169,7 +174,7 @@ dir group=bin mode=0755 owner=webservd path=var/nginx/logs
 dir group=bin mode=0755 owner=root path=\"var/nginx\"",
         );
 
-        let test_results = vec![
+        let test_results = [
             Dir {
                 group: String::from("bin"),
                 mode: String::from("0755"),
@@ -254,6 +259,7 @@ file path=usr/lib/golang/1.16/src/runtime/runtime-gdb_test.go.~1~");
                     additional_identifiers: vec![
                         Digest {
                             hash: "7288afc78233791bb8e13b3e13aa4f0b4b1d6ee8".to_string(),
+                            source: DigestSource::GzipCompressed,
                             ..Digest::default()
                         },
                         Digest {
@@ -298,6 +304,7 @@ file path=usr/lib/golang/1.16/src/runtime/runtime-gdb_test.go.~1~");
                     additional_identifiers: vec![
                         Digest {
                             hash: "2f82b51db9cbba0705cb680e5aa0f11ff237009b".to_string(),
+                            source: DigestSource::GzipCompressed,
                             ..Digest::default()
                         },
                         Digest {
@@ -330,11 +337,11 @@ file path=usr/lib/golang/1.16/src/runtime/runtime-gdb_test.go.~1~");
                         key: "pkg.size".to_string(),
                         value: "2844".to_string(),
                     },
-                    Property {
-                        key: "restart_fmri".to_string(),
-                        value: "svc:/system/manifest-import:default".to_string(),
-                    },
                 ],
+                actuators: Actuators {
+                    restart_fmri: vec!["svc:/system/manifest-import:default".to_string()],
+                    ..Actuators::default()
+                },
                 ..File::default()
             },
             File {
@@ -346,6 +353,7 @@ file path=usr/lib/golang/1.16/src/runtime/runtime-gdb_test.go.~1~");
                     additional_identifiers: vec![
                         Digest {
                             hash: "c2e2e4cf82ec527800a2170d9e2565b75d557012".to_string(),
+                            source: DigestSource::GzipCompressed,
                             ..Digest::default()
                         },
                         Digest {
@@ -390,6 +398,7 @@ file path=usr/lib/golang/1.16/src/runtime/runtime-gdb_test.go.~1~");
                     additional_identifiers: vec![
                         Digest {
                             hash: "50b7bcf6c555b8e9bde1eacd2c3d5c34a757c312".to_string(),
+                            source: DigestSource::GzipCompressed,
                             ..Digest::default()
                         },
                         Digest {
@@ -434,6 +443,7 @@ file path=usr/lib/golang/1.16/src/runtime/runtime-gdb_test.go.~1~");
                     additional_identifiers: vec![
                         Digest {
                             hash: "2df27ca83841b9c8e38c5aa30760372773166928".to_string(),
+                            source: DigestSource::GzipCompressed,
                             ..Digest::default()
                         },
                         Digest {
@@ -479,6 +489,7 @@ file path=usr/lib/golang/1.16/src/runtime/runtime-gdb_test.go.~1~");
                     additional_identifiers: vec![
                         Digest {
                             hash: "530616dc345f6acf0aea26db06e56aa41b2f510d".to_string(),
+                            source: DigestSource::GzipCompressed,
                             ..Digest::default()
                         },
                         Digest {
@@ -524,6 +535,7 @@ file path=usr/lib/golang/1.16/src/runtime/runtime-gdb_test.go.~1~");
                     additional_identifiers: vec![
                         Digest {
                             hash: "00d285c15dd65f24c4c89d5790094c38432a1ac6".to_string(),
+                            source: DigestSource::GzipCompressed,
                             ..Digest::default()
                         },
                         Digest {
@@ -569,6 +581,7 @@ file path=usr/lib/golang/1.16/src/runtime/runtime-gdb_test.go.~1~");
                     additional_identifiers: vec![
                         Digest {
                             hash: "0f2588ac25780698ea7ebeac3ea0e9041502d501".to_string(),
+                            source: DigestSource::GzipCompressed,
                             ..Digest::default()
                         },
                         Digest {
@@ -614,6 +627,7 @@ file path=usr/lib/golang/1.16/src/runtime/runtime-gdb_test.go.~1~");
                     additional_identifiers: vec![
                         Digest {
                             hash: "325af5a4b735284a3cdfd3b04bd249ff22334965".to_string(),
+                            source: DigestSource::GzipCompressed,
                             ..Digest::default()
                         },
                         Digest {
@@ -659,6 +673,7 @@ file path=usr/lib/golang/1.16/src/runtime/runtime-gdb_test.go.~1~");
                     additional_identifiers: vec![
                         Digest {
                             hash: "adacb374c514459417f07cacd4f8bf60644c9651".to_string(),
+                            source: DigestSource::GzipCompressed,
                             ..Digest::default()
                         },
                         Digest {
@@ -704,6 +719,7 @@ file path=usr/lib/golang/1.16/src/runtime/runtime-gdb_test.go.~1~");
                     additional_identifiers: vec![
                         Digest {
                             hash: "2c75c59e0de9208a9b96460d0566e5686708310c".to_string(),
+                            source: DigestSource::GzipCompressed,
                             ..Digest::default()
                         },
                         Digest {
@@ -749,6 +765,7 @@ file path=usr/lib/golang/1.16/src/runtime/runtime-gdb_test.go.~1~");
                     additional_identifiers: vec![
                         Digest {
                             hash: "62320c6c207a26bf9c68c39d0372f4d4b97b905f".to_string(),
+                            source: DigestSource::GzipCompressed,
                             ..Digest::default()
                         },
                         Digest {
@@ -794,6 +811,7 @@ file path=usr/lib/golang/1.16/src/runtime/runtime-gdb_test.go.~1~");
                     additional_identifiers: vec![
                         Digest {
                             hash: "fd231cdd1a726fcb2abeba90b31cbf4c7df6df4d".to_string(),
+                            source: DigestSource::GzipCompressed,
                             ..Digest::default()
                         },
                         Digest {
@@ -839,6 +857,7 @@ file path=usr/lib/golang/1.16/src/runtime/runtime-gdb_test.go.~1~");
                     additional_identifiers: vec![
                         Digest {
                             hash: "3ab17dde089f1eac7abd37d8efd700b5139d70b2".to_string(),
+                            source: DigestSource::GzipCompressed,
                             ..Digest::default()
                         },
                         Digest {
@@ -922,6 +941,7 @@ file path=usr/lib/golang/1.16/src/runtime/runtime-gdb_test.go.~1~");
             assert_eq!(file.owner, test_results[pos].owner);
             assert_eq!(file.path, test_results[pos].path);
             assert_eq!(file.preserve, test_results[pos].preserve);
+            assert_eq!(file.actuators, test_results[pos].actuators);
             if let Some(payload_expected) = &test_results[pos].payload {
                 assert_ne!(file.payload, None);
                 assert_eq!(
@@ -969,6 +989,34 @@ file path=usr/lib/golang/1.16/src/runtime/runtime-gdb_test.go.~1~");
         }
     }
 
+    #[test]
+    fn parse_actuators() {
+        let manifest_string = String::from("file 72e0496a02e72e7380b0b62cdc8410108302876f chash=2f82b51db9cbba0705cb680e5aa0f11ff237009b group=sys mode=0444 owner=root path=lib/svc/manifest/network/http-nginx.xml pkg.content-hash=file:sha512t_256:c0c3640d6e61b53a3dc4228adff7532ec6b5d09bf1847991a3aaa5eb3e04d19a pkg.content-hash=gzip:sha512t_256:e1999bae58ef887d81dc686b794429a9dea0e7674b631c2a08f07fb9b34440e2 pkg.csize=1067 pkg.size=2844 restart_fmri=svc:/system/manifest-import:default
+file path=lib/svc/method/http-nginx refresh_fmri=svc:/network/http:nginx refresh_fmri=svc:/network/http:nginx-alt
+file path=usr/share/nginx/html/index.html");
+
+        let res = Manifest::parse_string(manifest_string);
+        assert!(res.is_ok(), "error during Manifest parsing: {:?}", res);
+        let manifest = res.unwrap();
+
+        assert_eq!(
+            manifest.files[0].actuators.restart_fmri,
+            vec!["svc:/system/manifest-import:default".to_string()]
+        );
+        assert!(manifest.files[0]
+            .properties
+            .iter()
+            .all(|p| p.key != "restart_fmri"));
+        assert_eq!(
+            manifest.files[1].actuators.refresh_fmri,
+            vec![
+                "svc:/network/http:nginx".to_string(),
+                "svc:/network/http:nginx-alt".to_string()
+            ]
+        );
+        assert!(manifest.files[2].actuators.is_empty());
+    }
+
     #[test]
     fn parse_dependency_actions() {
         let manifest_string = String::from("depend fmri=pkg:/system/library@0.5.11-2020.0.1.19563 type=require
@@ -977,7 +1025,7 @@ depend facet.version-lock.system/data/hardware-registry=true fmri=pkg:/system/da
 depend facet.version-lock.xvm=true fmri=xvm@0.5.11-2015.0.2.0 type=incorporate
 depend facet.version-lock.system/mozilla-nss=true fmri=system/mozilla-nss@3.51.1-2020.0.1.0 type=incorporate");
 
-        let test_results = vec![
+        let test_results = [
             Dependency {
                 fmri: "pkg:/system/library@0.5.11-2020.0.1.19563".to_string(),
                 dependency_type: "require".to_string(),
@@ -1035,7 +1083,7 @@ depend facet.version-lock.system/mozilla-nss=true fmri=system/mozilla-nss@3.51.1
                 dependency.dependency_type,
                 test_results[pos].dependency_type
             );
-            for (_, (key, facet)) in dependency.facets.iter().enumerate() {
+            for (key, facet) in dependency.facets.iter() {
                 let fres = test_results[pos].facets.get(key);
                 assert!(
                     fres.is_some(),
@@ -1069,7 +1117,7 @@ file Solaris/ManageCUPS.html mode=0444 \
      path=usr/lib/help/auths/locale/C/ManageCUPS.html",
         );
 
-        let file_results = vec![
+        let file_results = [
             File {
                 path: "usr/lib/cups/bin/desktop-print-management".to_string(),
                 mode: "0555".to_string(),
@@ -1117,7 +1165,7 @@ file Solaris/ManageCUPS.html mode=0444 \
             },
         ];
 
-        let link_results = vec![Link {
+        let link_results = [Link {
             path: "usr/lib/cups/backend/http".to_string(),
             target: "ipp".to_string(),
             ..Link::default()
@@ -1153,7 +1201,7 @@ file Solaris/ManageCUPS.html mode=0444 \
 link path=usr/lib/cups/пертинах/http target=blub",
         );
 
-        let link_results = vec![
+        let link_results = [
             Link {
                 path: "usr/lib/cups/пертинах/http".to_string(),
                 target: "Про".to_string(),
@@ -1175,4 +1223,1428 @@ link path=usr/lib/cups/пертинах/http target=blub",
             assert_eq!(link.target, link_results[pos].target);
         }
     }
+
+    #[derive(Debug, Default)]
+    struct RecordingServiceManager {
+        calls: Mutex<Vec<(String, String)>>,
+    }
+
+    impl ServiceManager for RecordingServiceManager {
+        fn restart(&self, fmri: &str) -> crate::image::Result<()> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(("restart".to_string(), fmri.to_string()));
+            Ok(())
+        }
+
+        fn refresh(&self, fmri: &str) -> crate::image::Result<()> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(("refresh".to_string(), fmri.to_string()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn run_actuators_once_per_service() {
+        let manifest_string = String::from(
+            "file path=lib/svc/manifest/network/http-nginx.xml restart_fmri=svc:/system/manifest-import:default
+file path=lib/svc/manifest/network/http-apache.xml restart_fmri=svc:/system/manifest-import:default
+file path=etc/nginx/nginx.conf refresh_fmri=svc:/network/http:nginx
+file path=usr/share/nginx/html/index.html",
+        );
+
+        let res = Manifest::parse_string(manifest_string);
+        assert!(res.is_ok(), "error during Manifest parsing: {:?}", res);
+        let manifest = res.unwrap();
+
+        let svc = Arc::new(RecordingServiceManager::default());
+        let image = Image::with_service_manager("/", svc.clone());
+        let res = image.run_actuators(&manifest.files);
+        assert!(res.is_ok(), "error running actuators: {:?}", res);
+
+        assert_eq!(
+            *svc.calls.lock().unwrap(),
+            vec![
+                (
+                    "refresh".to_string(),
+                    "svc:/network/http:nginx".to_string()
+                ),
+                (
+                    "restart".to_string(),
+                    "svc:/system/manifest-import:default".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_fmri_from_catalog_key() {
+        let fmri = Fmri::from_catalog_key("web/server/nginx@1.18.0,5.11-2020.0.1.0:20200421T195136Z");
+        assert!(fmri.is_ok(), "error parsing catalog key: {:?}", fmri);
+        let fmri = fmri.unwrap();
+
+        assert_eq!(fmri.publisher, None);
+        assert_eq!(fmri.stem, "web/server/nginx");
+        assert_eq!(
+            fmri.version,
+            Some(Version {
+                release: "1.18.0".to_string(),
+                build_release: Some("5.11".to_string()),
+                branch: Some("2020.0.1.0".to_string()),
+                timestamp: Some("20200421T195136Z".to_string()),
+            })
+        );
+        assert_eq!(
+            fmri.to_string(),
+            "pkg:/web/server/nginx@1.18.0,5.11-2020.0.1.0:20200421T195136Z"
+        );
+
+        let fmri = Fmri::from_catalog_key("system/library").unwrap();
+        assert_eq!(fmri.stem, "system/library");
+        assert_eq!(fmri.version, None);
+
+        assert!(Fmri::from_catalog_key("system/library@latest").is_err());
+        assert!(Fmri::from_catalog_key("system/library@0.5.11-").is_err());
+    }
+
+    #[test]
+    fn set_multiple_facets_and_variants() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut image = Image::new(dir.path());
+
+        let res = image.set_facets(hashmap! {
+            "doc".to_string() => false,
+            "locale.de".to_string() => true,
+        });
+        assert!(res.is_ok(), "error setting facets: {:?}", res);
+        // One configuration write per call, not one per facet or variant
+        assert_eq!(image.saves.load(std::sync::atomic::Ordering::SeqCst), 1);
+        let res = image.set_variants(hashmap! {
+            "variant.arch".to_string() => "i386".to_string(),
+            "variant.opensolaris.zone".to_string() => "global".to_string(),
+        });
+        assert!(res.is_ok(), "error setting variants: {:?}", res);
+        assert_eq!(image.saves.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        let image = Image::open(dir.path()).unwrap();
+        assert_eq!(image.facets().len(), 2);
+        assert_eq!(image.facets().get("doc"), Some(&false));
+        assert_eq!(image.facets().get("locale.de"), Some(&true));
+        assert_eq!(image.variants().len(), 2);
+        assert_eq!(
+            image.variants().get("variant.arch"),
+            Some(&"i386".to_string())
+        );
+    }
+
+    #[test]
+    fn manifest_display_version() {
+        let manifest = Manifest::parse_string(String::from(
+            "set name=pkg.fmri value=pkg://openindiana.org/web/server/nginx@1.18.0,5.11-2020.0.1.0:20200421T195136Z
+set name=pkg.human-version value=1.18.0-release",
+        ))
+        .unwrap();
+        assert_eq!(manifest.human_version(), Some("1.18.0-release".to_string()));
+        assert_eq!(
+            manifest.display_version(),
+            Some("1.18.0-release".to_string())
+        );
+
+        let manifest = Manifest::parse_string(String::from(
+            "set name=pkg.fmri value=pkg://openindiana.org/web/server/nginx@1.18.0,5.11-2020.0.1.0:20200421T195136Z",
+        ))
+        .unwrap();
+        assert_eq!(manifest.human_version(), None);
+        assert_eq!(
+            manifest.display_version(),
+            Some("1.18.0,5.11-2020.0.1.0:20200421T195136Z".to_string())
+        );
+    }
+
+    #[test]
+    fn memory_installed_db() {
+        let manifest = Manifest::parse_string(String::from(
+            "set name=pkg.fmri value=pkg://openindiana.org/web/server/nginx@1.18.0,5.11-2020.0.1.0:20200421T195136Z
+file path=usr/sbin/nginx mode=0555",
+        ))
+        .unwrap();
+        let fmri = Fmri::with_publisher(
+            "openindiana.org",
+            "web/server/nginx",
+            Some(Version::parse("1.18.0,5.11-2020.0.1.0:20200421T195136Z").unwrap()),
+        )
+        .unwrap();
+
+        // The image path does not exist so any write to disk would fail
+        let image = Image::with_installed_db(
+            "/nonexistent/image",
+            Arc::new(MemoryInstalledDb::default()),
+        );
+        let db = image.installed_db();
+        assert!(db.list().unwrap().is_empty());
+
+        let res = db.add(&fmri, &manifest, true);
+        assert!(res.is_ok(), "error recording installed package: {:?}", res);
+        assert_eq!(image.installed_db().list().unwrap(), vec![fmri.clone()]);
+        assert_eq!(
+            db.get("web/server/nginx").unwrap().unwrap().manifest,
+            manifest
+        );
+
+        assert!(db.remove("web/server/nginx").is_ok());
+        assert!(db.remove("web/server/nginx").is_err());
+        assert!(db.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn file_installed_db() {
+        let dir = tempfile::tempdir().unwrap();
+        let fmri = Fmri::from_catalog_key("system/library@0.5.11-2020.0.1.19563").unwrap();
+
+        let image = Image::new(dir.path());
+        let res = image.installed_db().add(&fmri, &Manifest::new(), true);
+        assert!(res.is_ok(), "error recording installed package: {:?}", res);
+        assert!(dir.path().join("pkg6.installed.json").exists());
+
+        let image = Image::new(dir.path());
+        assert_eq!(image.installed_db().list().unwrap(), vec![fmri]);
+    }
+
+    #[test]
+    fn transform_edit_with_backreference() {
+        let mut manifest = Manifest::parse_string(String::from(
+            "file path=usr/local/bin/nginx mode=0555 owner=root group=bin
+file path=usr/local/share/man/man8/nginx.8 mode=0444
+file path=etc/nginx/nginx.conf mode=0644 preserve=true
+dir path=usr/local/bin",
+        ))
+        .unwrap();
+
+        let res = Transform::parse("<transform file path=usr/local/.* -> edit path usr/local/(.*) usr/$1>");
+        assert!(res.is_ok(), "error parsing transform: {:?}", res);
+        let res = res.unwrap().apply(&mut manifest);
+        assert!(res.is_ok(), "error applying transform: {:?}", res);
+
+        let paths: Vec<&str> = manifest.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "usr/bin/nginx",
+                "usr/share/man/man8/nginx.8",
+                "etc/nginx/nginx.conf"
+            ]
+        );
+        assert_eq!(manifest.files[0].mode, "0555");
+        assert_eq!(manifest.files[0].owner, "root");
+        assert!(manifest.files[2].preserve);
+        // Only file actions were selected
+        assert_eq!(manifest.directories[0].path, "usr/local/bin");
+
+        // pkgmogrify style \1 backreferences work as well
+        Transform::parse(r"<transform dir -> edit path ^usr/local/(.*) opt/\1>")
+            .unwrap()
+            .apply(&mut manifest)
+            .unwrap();
+        assert_eq!(manifest.directories[0].path, "opt/bin");
+
+        assert!(Transform::parse("<transform file -> edit path usr/local>").is_err());
+        assert!(Transform::parse("<transform file path=usr/.*>").is_err());
+    }
+
+    #[test]
+    fn fmri_without_publisher() {
+        let fmri = Fmri::with_publisher(
+            "openindiana.org",
+            "web/server/nginx",
+            Some(Version::parse("1.18.0,5.11-2020.0.1.0:20200421T195136Z").unwrap()),
+        )
+        .unwrap();
+        let stripped = fmri.without_publisher();
+
+        assert_eq!(stripped.publisher, None);
+        assert_eq!(stripped.stem, fmri.stem);
+        assert_eq!(stripped.version, fmri.version);
+        assert_eq!(
+            stripped,
+            Fmri::with_publisher("omnios", "web/server/nginx", fmri.version.clone())
+                .unwrap()
+                .without_publisher()
+        );
+    }
+
+    #[test]
+    fn parse_numeric_owner_and_group() {
+        let manifest = Manifest::parse_string(String::from(
+            "file path=etc/shadow owner=0 group=3 mode=0400
+file path=etc/passwd owner=root group=sys mode=0444
+dir path=var/empty owner=60001 group=60001 mode=0755",
+        ))
+        .unwrap();
+
+        assert_eq!(manifest.files[0].owner, "0");
+        assert_eq!(manifest.files[0].uid, Some(0));
+        assert_eq!(manifest.files[0].group, "3");
+        assert_eq!(manifest.files[0].gid, Some(3));
+        assert_eq!(manifest.files[1].owner, "root");
+        assert_eq!(manifest.files[1].uid, None);
+        assert_eq!(manifest.files[1].gid, None);
+        assert_eq!(manifest.directories[0].uid, Some(60001));
+        assert_eq!(manifest.directories[0].gid, Some(60001));
+    }
+
+    #[test]
+    fn fmri_rejects_empty_stem() {
+        assert!(Fmri::new("").is_err());
+        assert!(Fmri::new("  ").is_err());
+        assert!(Fmri::with_version("", Version::parse("1.0").unwrap()).is_err());
+        assert!(Fmri::with_publisher("openindiana.org", "", None).is_err());
+        assert!(Fmri::with_publisher("", "web/server/nginx", None).is_err());
+        assert!(Fmri::from_catalog_key("@1.0").is_err());
+        assert!(Fmri::from_catalog_key("").is_err());
+
+        assert!(Fmri::new("web/server/nginx").is_ok());
+    }
+
+    #[test]
+    fn facet_normalized_key() {
+        let facet = Facet {
+            name: "version-lock.system/data/hardware-registry".to_string(),
+            value: "true".to_string(),
+        };
+        let padded = Facet {
+            name: " version-lock.system/data/hardware-registry\t".to_string(),
+            value: " true ".to_string(),
+        };
+        let prefixed = Facet {
+            name: "facet.version-lock.system/data/hardware-registry".to_string(),
+            value: "true".to_string(),
+        };
+        assert_eq!(facet.normalized_key(), padded.normalized_key());
+        assert_eq!(facet.normalized_key(), prefixed.normalized_key());
+
+        let mut dep = Dependency::default();
+        assert!(dep.add_facet(padded));
+        assert!(!dep.add_facet(prefixed));
+        assert_eq!(dep.facets.len(), 1);
+        assert_eq!(
+            dep.facets["version-lock.system/data/hardware-registry"],
+            facet
+        );
+        assert!(dep.remove_facet(facet));
+        assert!(dep.facets.is_empty());
+    }
+
+    #[test]
+    fn parse_hash_with_algorithm_prefix() {
+        let manifest = Manifest::parse_string(String::from(
+            "file hash=sha256:6b86b273ff34fce19d6b804eff5a3f5747ada4eaa22f1d49c01e52ddb7875b4b chash=sha256t:d4735e3a265e16eee03f59718b9b5d03019c07d8b6c51f90da3a666eec13ab35 path=usr/bin/a owner=root group=bin mode=0555
+file sha256t:4e07408562bedb8b60ce05c1decfe3ad16b72230967de01f640b7e4729b49fce path=usr/bin/b owner=root group=bin mode=0555
+file 1d4c8b09b8d1e5d4c0de1a8b6b7a1b6e0e5f8a2c path=usr/bin/c owner=root group=bin mode=0555",
+        ));
+        assert!(manifest.is_ok(), "error during Manifest parsing: {:?}", manifest);
+        let manifest = manifest.unwrap();
+
+        let payload = manifest.files[0].payload.as_ref().unwrap();
+        assert_eq!(
+            payload.primary_identifier,
+            Digest {
+                hash: "6b86b273ff34fce19d6b804eff5a3f5747ada4eaa22f1d49c01e52ddb7875b4b"
+                    .to_string(),
+                algorithm: DigestAlgorithm::SHA256,
+                source: DigestSource::PrimaryPayloadHash,
+            }
+        );
+        assert_eq!(
+            payload.additional_identifiers,
+            vec![Digest {
+                hash: "d4735e3a265e16eee03f59718b9b5d03019c07d8b6c51f90da3a666eec13ab35"
+                    .to_string(),
+                algorithm: DigestAlgorithm::SHA256,
+                source: DigestSource::GzipCompressed,
+            }]
+        );
+        assert!(manifest.files[0].properties.is_empty());
+
+        let payload = manifest.files[1].payload.as_ref().unwrap();
+        assert_eq!(payload.primary_identifier.algorithm, DigestAlgorithm::SHA256);
+        let payload = manifest.files[2].payload.as_ref().unwrap();
+        assert_eq!(payload.primary_identifier.algorithm, DigestAlgorithm::SHA1);
+
+        let act = Action::from(manifest.files[0].clone());
+        assert_eq!(
+            act.payload_string,
+            "sha256:6b86b273ff34fce19d6b804eff5a3f5747ada4eaa22f1d49c01e52ddb7875b4b"
+        );
+    }
+
+    #[test]
+    fn digest_algorithm_hex_len() {
+        assert_eq!(DigestAlgorithm::SHA1.hex_len(), 40);
+        assert_eq!(DigestAlgorithm::SHA512Half.hex_len(), 64);
+        assert_eq!(DigestAlgorithm::SHA512.hex_len(), 128);
+
+        let all = DigestAlgorithm::all();
+        assert_eq!(all.len(), 7);
+        assert!(all.contains(&DigestAlgorithm::SHA1));
+        assert!(all.contains(&DigestAlgorithm::SHA3512));
+
+        for algo in all {
+            let digest = Digest::from_bytes(b"pkg6", algo.clone(), DigestSource::default()).unwrap();
+            assert_eq!(digest.hash.len(), algo.hex_len(), "{}", algo);
+        }
+    }
+
+    #[test]
+    fn canonical_bytes_ignore_formatting() {
+        let a = Manifest::parse_string(String::from(
+            r#"set name=pkg.fmri value=pkg://openindiana.org/web/server/nginx@1.18.0,5.11-2020.0.1.0:20200421T195136Z
+set name=pkg.summary value="Nginx Webserver"
+dir path=etc/nginx owner=root group=bin mode=0755
+file path=usr/sbin/nginx owner=root group=bin mode=0555 \
+    pkg.depend.bypass-generate=.*
+depend fmri=library/pcre type=require"#,
+        ))
+        .unwrap();
+        let b = Manifest::parse_string(String::from(
+            r#"depend   type=require fmri=library/pcre
+set name=pkg.summary  value="Nginx Webserver"
+set name=pkg.fmri value="pkg://openindiana.org/web/server/nginx@1.18.0,5.11-2020.0.1.0:20200421T195136Z"
+file owner=root path=usr/sbin/nginx group=bin mode=0555 pkg.depend.bypass-generate=.*
+dir mode=0755 path=etc/nginx owner=root group=bin
+"#,
+        ))
+        .unwrap();
+
+        assert_eq!(a.canonical_bytes(), b.canonical_bytes());
+
+        let canonical = String::from_utf8(a.canonical_bytes()).unwrap();
+        assert!(canonical.contains("set name=pkg.summary value=\"Nginx Webserver\"\n"));
+
+        let c = Manifest::parse_string(String::from(
+            "dir path=etc/nginx owner=root group=bin mode=0700",
+        ))
+        .unwrap();
+        assert_ne!(a.canonical_bytes(), c.canonical_bytes());
+    }
+
+    #[test]
+    fn manifest_string_round_trip() {
+        let manifest = Manifest::parse_string(String::from(
+            r#"set name=pkg.fmri value=pkg://openindiana.org/web/server/nginx@1.18.0,5.11-2020.0.1.0:20200421T195136Z
+set name=com.oracle.info.name value=nginx value=test
+set name=pkg.summary value="Nginx Webserver"
+set name=info.classification value="org.opensolaris.category.2008:Web Services/Application and Web Servers"
+set name=variant.arch value=i386 optional=testing optionalWithString="test ing"
+set name=pkg.description value="The \"nginx\" webserver"
+dir group=bin mode=0755 owner=root path=etc/nginx
+dir group=bin mode=0755 owner=webservd path=var/nginx/logs
+dir group=sys mode=0755 owner=root path=kernel/sparcv9 variant.arch=sparc
+file 72e0496a02e72e7380b0b62cdc8410108302876f chash=2f82b51db9cbba0705cb680e5aa0f11ff237009b group=sys mode=0444 owner=root path=lib/svc/manifest/network/http-nginx.xml pkg.content-hash=file:sha512t_256:c0c3640d6e61b53a3dc4228adff7532ec6b5d09bf1847991a3aaa5eb3e04d19a pkg.content-hash=gzip:sha512t_256:e1999bae58ef887d81dc686b794429a9dea0e7674b631c2a08f07fb9b34440e2 pkg.csize=1067 pkg.size=2844 restart_fmri=svc:/system/manifest-import:default
+file cbf596ddb3433a8e0d325f3c188bec9c1bb746b3 chash=2df27ca83841b9c8e38c5aa30760372773166928 group=bin mode=0644 owner=root path=etc/nginx/fastcgi.conf pkg.content-hash=file:sha512t_256:d260c064680ec58135d9a290ed3cfd64274db769701ab3df2bfdeb653a864518 pkg.content-hash=gzip:sha512t_256:4924c0f4bdc37b832afd281ad07b0bf339c8c3a0e2d95e076998d46fab76a084 pkg.csize=448 pkg.size=1077 preserve=true
+depend fmri=pkg:/system/library@0.5.11-2020.0.1.19563 type=require
+depend facet.version-lock.xvm=true fmri=xvm@0.5.11-2015.0.2.0 type=incorporate
+depend fmri=web/server/nginx fmri=web/server/nginx-local type=require-any
+file group=bin mode=0644 overlay=allow owner=root path=etc/nginx/mime.types preserve=renamenew
+file Solaris/ManageCUPS.html mode=0444 path=usr/share/cups/doc-root/help/manage-cups.html
+link path=usr/share/nginx/html target=../../../var/nginx/html
+link path=usr/bin/nginx target=../sbin/nginx variant.arch=i386 variant.arch=sparc"#,
+        ));
+        assert!(manifest.is_ok(), "error during Manifest parsing: {:?}", manifest);
+        let manifest = manifest.unwrap();
+
+        let serialized = manifest.to_manifest_string();
+        assert!(serialized.contains(r#"value="The \"nginx\" webserver""#));
+
+        let reparsed = Manifest::parse_string(serialized.clone());
+        assert!(reparsed.is_ok(), "error during Manifest parsing: {:?}", reparsed);
+        let reparsed = reparsed.unwrap();
+        assert_eq!(reparsed.to_manifest_string(), serialized);
+
+        assert_eq!(reparsed, manifest);
+        assert_eq!(
+            reparsed.directories[2].properties,
+            vec![Property {
+                key: "variant.arch".to_string(),
+                value: "sparc".to_string(),
+            }]
+        );
+        assert_eq!(
+            Action::from(reparsed.links[1].clone()).get_values("variant.arch"),
+            vec!["i386", "sparc"]
+        );
+        assert_eq!(
+            Action::from(reparsed.dependencies[2].clone()).get_values("fmri"),
+            vec!["web/server/nginx", "web/server/nginx-local"]
+        );
+        let mime_types = Action::from(reparsed.files[2].clone());
+        assert_eq!(mime_types.get_values("preserve"), vec!["renamenew"]);
+        assert_eq!(mime_types.get_values("overlay"), vec!["allow"]);
+        assert!(serialized.contains(
+            "file Solaris/ManageCUPS.html mode=0444 path=usr/share/cups/doc-root/help/manage-cups.html\n"
+        ));
+        assert_eq!(
+            reparsed.files[3].get_original_path(),
+            Some("Solaris/ManageCUPS.html".to_string())
+        );
+
+        let mut written = Vec::new();
+        manifest.write_to(&mut written).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), serialized);
+    }
+
+    #[test]
+    fn parse_single_quoted_values() {
+        let manifest = Manifest::parse_string(String::from(
+            r#"set name=pkg.summary value='foo bar'
+set name=pkg.description value="'already double quoted'"
+set name=info.upstream value=don't
+set name=com.oracle.info.name value="it's 'nginx'!"
+set name=com.oracle.info.description value="'foo' and 'bar'""#,
+        ));
+        assert!(manifest.is_ok(), "error during Manifest parsing: {:?}", manifest);
+        let manifest = manifest.unwrap();
+
+        assert_eq!(manifest.attributes[0].values, vec!["foo bar".to_string()]);
+        assert_eq!(
+            manifest.attributes[1].values,
+            vec!["already double quoted".to_string()]
+        );
+        assert_eq!(manifest.attributes[2].values, vec!["don't".to_string()]);
+        assert_eq!(
+            manifest.attributes[3].values,
+            vec!["it's 'nginx'!".to_string()]
+        );
+        assert_eq!(
+            manifest.attributes[4].values,
+            vec!["'foo' and 'bar'".to_string()]
+        );
+    }
+
+    #[test]
+    fn parse_license_actions() {
+        let manifest = Manifest::parse_string(String::from(
+            "license 0e2b7e5a7ce1c8d9f0a5e43a0e5e3c3ad0c8b4e6 chash=b4a2c5a4e1f0e3e1c5a0d6d91d4ea95b2e2e1c35 license=Apache-2.0 must-accept=true pkg.csize=4082 pkg.size=11358
+license license=\"BSD-like license\" must-display=true",
+        ));
+        assert!(manifest.is_ok(), "error during Manifest parsing: {:?}", manifest);
+        let manifest = manifest.unwrap();
+
+        assert_eq!(manifest.licenses.len(), 2);
+
+        let apache = &manifest.licenses[0];
+        let payload = apache.payload.as_ref().unwrap();
+        assert_eq!(
+            payload.primary_identifier.hash,
+            "0e2b7e5a7ce1c8d9f0a5e43a0e5e3c3ad0c8b4e6"
+        );
+        assert_eq!(payload.additional_identifiers.len(), 1);
+        assert_eq!(apache.license, "Apache-2.0");
+        assert!(apache.must_accept);
+        assert!(!apache.must_display);
+        assert_eq!(
+            apache.properties[1],
+            Property {
+                key: "pkg.size".to_string(),
+                value: "11358".to_string(),
+            }
+        );
+
+        assert_eq!(
+            manifest.licenses[1],
+            License {
+                license: "BSD-like license".to_string(),
+                must_display: true,
+                ..License::default()
+            }
+        );
+    }
+
+    #[test]
+    fn transform_abort() {
+        let transform = Transform::parse("<transform file path=usr/local/.* -> abort>").unwrap();
+
+        let mut manifest = Manifest::parse_string(String::from(
+            "file path=usr/bin/nginx owner=root group=bin mode=0555",
+        ))
+        .unwrap();
+        assert!(transform.apply(&mut manifest).is_ok());
+
+        let mut manifest = Manifest::parse_string(String::from(
+            "file path=usr/bin/nginx owner=root group=bin mode=0555
+file path=usr/local/bin/nginx owner=root group=bin mode=0555",
+        ))
+        .unwrap();
+        let res = transform.apply(&mut manifest);
+        assert!(res.is_err());
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .contains("path=usr/local/bin/nginx"));
+    }
+
+    #[test]
+    fn parse_user_and_group_actions() {
+        let manifest = Manifest::parse_string(String::from(
+            "group gid=60 groupname=webservd
+user ftpuser=false gcos-field=\"WebServer Reserved UID\" group=webservd home-dir=/ login-shell=/bin/sh password=*LK* uid=80 username=webservd group-list=daemon group-list=sys variant.opensolaris.zone=global",
+        ));
+        assert!(manifest.is_ok(), "error during Manifest parsing: {:?}", manifest);
+        let manifest = manifest.unwrap();
+
+        assert_eq!(
+            manifest.groups,
+            vec![Group {
+                groupname: "webservd".to_string(),
+                gid: "60".to_string(),
+                ..Group::default()
+            }]
+        );
+        assert_eq!(
+            manifest.users,
+            vec![User {
+                username: "webservd".to_string(),
+                password: "*LK*".to_string(),
+                uid: "80".to_string(),
+                group: "webservd".to_string(),
+                gcos_field: "WebServer Reserved UID".to_string(),
+                home_dir: "/".to_string(),
+                login_shell: "/bin/sh".to_string(),
+                group_list: vec!["daemon".to_string(), "sys".to_string()],
+                ftpuser: false,
+                properties: vec![Property {
+                    key: "variant.opensolaris.zone".to_string(),
+                    value: "global".to_string(),
+                }],
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_driver_actions() {
+        let manifest = Manifest::parse_string(String::from(
+            r#"driver name=e1000g alias=pci8086,1000 alias=pci8086,1001 alias=pciex8086,10d3 clone_perms="e1000g 0666 root sys" perms="* 0666 root sys" perms="e1000g* 0600 root sys" variant.arch=i386"#,
+        ));
+        assert!(manifest.is_ok(), "error during Manifest parsing: {:?}", manifest);
+        let manifest = manifest.unwrap();
+
+        assert_eq!(
+            manifest.drivers,
+            vec![Driver {
+                name: "e1000g".to_string(),
+                alias: vec![
+                    "pci8086,1000".to_string(),
+                    "pci8086,1001".to_string(),
+                    "pciex8086,10d3".to_string()
+                ],
+                perms: vec![
+                    "* 0666 root sys".to_string(),
+                    "e1000g* 0600 root sys".to_string()
+                ],
+                properties: vec![
+                    Property {
+                        key: "clone_perms".to_string(),
+                        value: "e1000g 0666 root sys".to_string(),
+                    },
+                    Property {
+                        key: "variant.arch".to_string(),
+                        value: "i386".to_string(),
+                    },
+                ],
+                ..Driver::default()
+            }]
+        );
+
+        let reparsed = Manifest::parse_string(manifest.to_manifest_string()).unwrap();
+        assert_eq!(reparsed.drivers[0].alias, manifest.drivers[0].alias);
+        assert_eq!(reparsed.drivers[0].perms, manifest.drivers[0].perms);
+    }
+
+    #[test]
+    fn parse_fmri_forms() {
+        let full = Fmri::parse("pkg://openindiana.org/system/library@0.5.11-2020.0.1.19563").unwrap();
+        let scheme = Fmri::parse("pkg:/system/library@0.5.11-2020.0.1.19563").unwrap();
+        let plain = Fmri::parse("system/library@0.5.11-2020.0.1.19563").unwrap();
+
+        assert_eq!(full.publisher, Some("openindiana.org".to_string()));
+        assert_eq!(scheme.publisher, None);
+        assert_eq!(full.without_publisher(), scheme);
+        assert_eq!(scheme, plain);
+        assert_eq!(plain.stem, "system/library");
+        assert_eq!(
+            plain.version,
+            Some(Version::parse("0.5.11-2020.0.1.19563").unwrap())
+        );
+
+        let xvm: Fmri = "xvm@0.5.11-2015.0.2.0".parse().unwrap();
+        assert_eq!(xvm.stem, "xvm");
+        assert_eq!(Fmri::parse("pkg:/xvm").unwrap(), Fmri::new("xvm").unwrap());
+
+        assert!(Fmri::parse("pkg://openindiana.org").is_err());
+        assert!(Fmri::parse("pkg:///xvm").is_err());
+        assert!(Fmri::parse("pkg:/@1.0").is_err());
+    }
+
+    #[test]
+    fn transform_set_add_delete_drop() {
+        let mut manifest = Manifest::parse_string(String::from(
+            "file path=usr/share/doc/nginx/README mode=0644 owner=root group=bin
+file path=usr/share/doc/nginx/CHANGES mode=0644 owner=root group=bin
+file path=usr/bin/nginx mode=0755 owner=root group=bin
+file path=usr/share/man/man8/nginx.8 mode=0644 owner=root group=bin
+dir path=usr/share/doc/nginx
+depend fmri=library/pcre type=require facet.devel=true
+set name=info.classification value=org.opensolaris.category.2008:Web value=legacy",
+        ))
+        .unwrap();
+
+        let transforms: Vec<Transform> = [
+            "<transform file path=usr/share/doc/.* -> drop>",
+            "<transform file path=usr/share/man/.* -> set mode 0444>",
+            "<transform file path=usr/bin/.* -> add restart_fmri svc:/network/http:nginx>",
+            "<transform set name=info.classification -> delete value legacy>",
+        ]
+        .iter()
+        .map(|rule| Transform::parse(rule).unwrap())
+        .collect();
+        let res = apply_all(&transforms, &mut manifest);
+        assert!(res.is_ok(), "error applying transforms: {:?}", res);
+
+        let paths: Vec<&str> = manifest.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec!["usr/bin/nginx", "usr/share/man/man8/nginx.8"]);
+        assert_eq!(manifest.files[0].mode, "0755");
+        assert_eq!(
+            manifest.files[0].actuators.restart_fmri,
+            vec!["svc:/network/http:nginx".to_string()]
+        );
+        assert_eq!(manifest.files[1].mode, "0444");
+        // Only file actions were dropped
+        assert_eq!(manifest.directories.len(), 1);
+        assert_eq!(
+            manifest.attributes[0].values,
+            vec!["org.opensolaris.category.2008:Web".to_string()]
+        );
+
+        assert!(Transform::parse("<transform file -> set mode>").is_err());
+        assert!(Transform::parse("<transform file -> drop now>").is_err());
+    }
+
+    #[test]
+    fn filter_manifest_by_variants_and_facets() {
+        let manifest = Manifest::parse_string(String::from(
+            "set name=pkg.summary value=Kernel
+set name=pkg.description value=\"SPARC kernel\" variant.arch=sparc
+file path=kernel/amd64/unix owner=root group=sys mode=0755 variant.arch=i386
+file path=kernel/sparcv9/unix owner=root group=sys mode=0755 variant.arch=sparc
+file path=usr/share/man/man1/ls.1 owner=root group=bin mode=0444 facet.doc.man=true
+file path=usr/share/doc/kernel/README owner=root group=bin mode=0444 facet.doc=true
+file path=usr/share/locale/de/kernel.mo owner=root group=bin mode=0444 facet.locale.de=true
+dir path=kernel owner=root group=sys mode=0755 variant.arch=i386 variant.arch=sparc
+dir path=kernel/sparcv9 owner=root group=sys mode=0755 variant.arch=sparc",
+        ))
+        .unwrap();
+
+        let variants = hashmap! {
+            "variant.arch".to_string() => "i386".to_string(),
+        };
+        let facets = hashmap! {
+            "doc".to_string() => false,
+            "doc.man".to_string() => true,
+        };
+        let filtered = manifest.filter(&variants, &facets);
+
+        let paths: Vec<&str> = filtered.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "kernel/amd64/unix",
+                "usr/share/man/man1/ls.1",
+                "usr/share/locale/de/kernel.mo"
+            ]
+        );
+        assert_eq!(filtered.directories.len(), 1);
+        assert_eq!(filtered.directories[0].path, "kernel");
+        assert_eq!(filtered.attributes.len(), 1);
+        assert_eq!(filtered.attributes[0].key, "pkg.summary");
+    }
+
+    #[test]
+    fn fmri_matches_pattern() {
+        let nginx = Fmri::parse("pkg://openindiana.org/web/server/nginx@1.18.0,5.11-2020.0.1.0").unwrap();
+        let less = Fmri::parse("pkg://openindiana.org/text/less@5.51-2020.0.1.1").unwrap();
+        let local = Fmri::parse("pkg:/web/server/nginx-local@1.18.0").unwrap();
+
+        assert!(less.matches_pattern("text/*"));
+        assert!(!nginx.matches_pattern("text/*"));
+
+        assert!(nginx.matches_pattern("*nginx*"));
+        assert!(local.matches_pattern("*nginx*"));
+        assert!(!less.matches_pattern("*nginx*"));
+
+        assert!(nginx.matches_pattern("pkg://openindiana.org/web/*@1.18.*"));
+        assert!(nginx.matches_pattern("pkg://openindiana.org/web/*@1.18"));
+        assert!(!nginx.matches_pattern("pkg://openindiana.org/web/*@1.1"));
+        assert!(!nginx.matches_pattern("pkg://openindiana.org/web/*@1.19.*"));
+        assert!(!nginx.matches_pattern("pkg://omnios/web/*@1.18.*"));
+        assert!(!local.matches_pattern("pkg://openindiana.org/web/*"));
+        assert!(local.matches_pattern("pkg://*/web/*"));
+
+        assert!(nginx.matches_pattern("nginx"));
+        assert!(nginx.matches_pattern("server/ngin?"));
+        assert!(!nginx.matches_pattern("pkg:/nginx"));
+        assert!(nginx.matches_pattern("pkg:/web/server/nginx"));
+    }
+
+    #[test]
+    fn group_dependencies_by_type() {
+        let manifest = Manifest::parse_string(String::from("depend fmri=pkg:/system/library@0.5.11-2020.0.1.19563 type=require
+depend fmri=pkg:/system/file-system/nfs@0.5.11,5.11-2020.0.1.19951 type=incorporate
+depend facet.version-lock.system/data/hardware-registry=true fmri=pkg:/system/data/hardware-registry@2020.2.22,5.11-2020.0.1.19951 type=incorporate
+depend facet.version-lock.xvm=true fmri=xvm@0.5.11-2015.0.2.0 type=incorporate
+depend facet.version-lock.system/mozilla-nss=true fmri=system/mozilla-nss@3.51.1-2020.0.1.0 type=incorporate"))
+        .unwrap();
+
+        let by_type = manifest.dependencies_by_type();
+        assert_eq!(by_type.len(), 2);
+        assert_eq!(by_type["require"].len(), 1);
+        assert_eq!(
+            by_type["require"][0].fmri,
+            "pkg:/system/library@0.5.11-2020.0.1.19563"
+        );
+        assert_eq!(by_type["incorporate"].len(), 4);
+        assert!(!by_type.contains_key("optional"));
+    }
+
+    #[test]
+    fn version_ordering() {
+        let v = |s: &str| Version::parse(s).unwrap();
+
+        assert!(v("1.10.0") > v("1.9.0"));
+        assert!(v("1.9.0") < v("1.10.0"));
+        assert!(v("1.10") > v("1.9.9"));
+        assert!(v("1.9.0.1") > v("1.9.0"));
+        // The build release does not take part in the ordering
+        assert_eq!(v("0.5.11,5.11").cmp_ips(&v("0.5.11")), Ordering::Equal);
+        assert_eq!(v("0.5.11,5.12-1").cmp_ips(&v("0.5.11,5.11-1")), Ordering::Equal);
+        assert!(v("0.5.11,5.12-1") < v("0.5.11,5.11-2"));
+        assert!(v("0.5.11,5.11-2020.0.1.19951") > v("0.5.11,5.11-2020.0.1.9999"));
+        assert!(v("1.0-1:20200421T195136Z") > v("1.0-1:20190101T000000Z"));
+        assert_eq!(v("1.0-1").cmp_ips(&v("1.0-1")), Ordering::Equal);
+
+        let mut versions = vec![v("1.10.0"), v("1.2.0"), v("1.9.0")];
+        versions.sort();
+        assert_eq!(versions, vec![v("1.2.0"), v("1.9.0"), v("1.10.0")]);
+
+        let old = Fmri::parse("pkg:/web/server/nginx@1.9.0").unwrap();
+        let new = Fmri::parse("pkg:/web/server/nginx@1.10.0").unwrap();
+        assert!(new.is_newer_than(&old));
+        assert!(!old.is_newer_than(&new));
+        assert!(old.is_newer_than(&Fmri::new("web/server/nginx").unwrap()));
+    }
+
+    #[test]
+    fn version_timestamp() {
+        let version = Version::parse("1.18.0,5.11-2020.0.1.0:20200421T195136Z").unwrap();
+        let ts = version.timestamp();
+        assert_eq!(
+            ts,
+            Some(Timestamp {
+                year: 2020,
+                month: 4,
+                day: 21,
+                hour: 19,
+                minute: 51,
+                second: 36,
+            })
+        );
+        assert_eq!(ts.unwrap().to_string(), "20200421T195136Z");
+        assert!(ts > Timestamp::parse("20191231T235959Z"));
+
+        assert_eq!(Version::parse("1.18.0").unwrap().timestamp(), None);
+
+        assert!(Version::parse("1.18.0:2020-04-21").is_err());
+        assert!(Version::parse("1.18.0:20201321T195136Z").is_err());
+        assert!(Version::parse("1.18.0:20200421T195136").is_err());
+        assert_eq!(Timestamp::parse("2020042lT195136Z"), None);
+    }
+
+    #[test]
+    fn compute_digest_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("abc");
+        std::fs::write(&path, b"abc").unwrap();
+
+        let sha1 = Digest::from_file(&path, DigestAlgorithm::SHA1, DigestSource::UncompressedFile);
+        assert!(sha1.is_ok(), "error computing digest: {:?}", sha1);
+        assert_eq!(
+            sha1.unwrap(),
+            Digest {
+                hash: "a9993e364706816aba3e25717850c26c9cd0d89d".to_string(),
+                algorithm: DigestAlgorithm::SHA1,
+                source: DigestSource::UncompressedFile,
+            }
+        );
+
+        let sha512t_256 = Digest::from_file(
+            &path,
+            DigestAlgorithm::SHA512Half,
+            DigestSource::UncompressedFile,
+        )
+        .unwrap();
+        assert_eq!(
+            sha512t_256.hash,
+            "53048e2681941ef99b2e29b76b4c7dabe4c2d0c634fc6d46e0e2f13107e7af23"
+        );
+        assert_eq!(
+            sha512t_256.to_string(),
+            "file:sha512t_256:53048e2681941ef99b2e29b76b4c7dabe4c2d0c634fc6d46e0e2f13107e7af23"
+        );
+
+        let bytes = Digest::from_bytes(
+            b"abc",
+            DigestAlgorithm::SHA512Half,
+            DigestSource::UncompressedFile,
+        )
+        .unwrap();
+        assert_eq!(bytes, sha512t_256);
+
+        assert!(Digest::from_file(
+            dir.path().join("missing"),
+            DigestAlgorithm::SHA1,
+            DigestSource::default()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn installed_manifest() {
+        let manifest = Manifest::parse_string(String::from(
+            "set name=pkg.fmri value=pkg://openindiana.org/web/server/nginx@1.18.0,5.11-2020.0.1.0:20200421T195136Z
+file path=usr/sbin/nginx mode=0555
+file path=etc/nginx/nginx.conf mode=0644 preserve=true",
+        ))
+        .unwrap();
+        let fmri =
+            Fmri::parse("pkg://openindiana.org/web/server/nginx@1.18.0,5.11-2020.0.1.0:20200421T195136Z")
+                .unwrap();
+
+        let image = Image::with_installed_db(
+            "/nonexistent/image",
+            Arc::new(MemoryInstalledDb::default()),
+        );
+        assert_eq!(image.installed_manifest(&fmri).unwrap(), None);
+
+        image.installed_db().add(&fmri, &manifest, true).unwrap();
+
+        let res = image.installed_manifest(&fmri);
+        assert!(res.is_ok(), "error reading installed manifest: {:?}", res);
+        let installed = res.unwrap().unwrap();
+        assert_eq!(installed.files, manifest.files);
+
+        let stem_only = Fmri::new("web/server/nginx").unwrap();
+        assert!(image.installed_manifest(&stem_only).unwrap().is_some());
+        let other_version = Fmri::parse("pkg:/web/server/nginx@1.20.0").unwrap();
+        assert_eq!(image.installed_manifest(&other_version).unwrap(), None);
+    }
+
+    #[test]
+    fn parse_sha256_content_hash() {
+        let manifest = Manifest::parse_string(String::from(
+            "file 4b76e83bb4bb7c87176b72ef805fe78ecae60d2c group=bin mode=0555 owner=root path=usr/sbin/nginx pkg.content-hash=file:sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+        ));
+        assert!(manifest.is_ok(), "error during Manifest parsing: {:?}", manifest);
+        let manifest = manifest.unwrap();
+
+        let payload = manifest.files[0].payload.as_ref().unwrap();
+        assert_eq!(
+            payload.additional_identifiers,
+            vec![Digest {
+                hash: "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+                    .to_string(),
+                algorithm: DigestAlgorithm::SHA256,
+                source: DigestSource::UncompressedFile,
+            }]
+        );
+        assert_eq!(
+            Digest::from_bytes(b"abc", DigestAlgorithm::SHA256, DigestSource::UncompressedFile)
+                .unwrap(),
+            payload.additional_identifiers[0]
+        );
+
+        assert!(manifest.to_manifest_string().contains(
+            "pkg.content-hash=file:sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        ));
+
+        let manifest = Manifest::parse_string(String::from(
+            "file 4b76e83bb4bb7c87176b72ef805fe78ecae60d2c path=usr/sbin/nginx pkg.hash=gzip:sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+        ))
+        .unwrap();
+        assert!(manifest.files[0].properties.is_empty());
+        let payload = manifest.files[0].payload.as_ref().unwrap();
+        assert_eq!(payload.additional_identifiers[0].algorithm, DigestAlgorithm::SHA256);
+        assert_eq!(payload.additional_identifiers[0].source, DigestSource::GzipCompressed);
+        let serialized = manifest.to_manifest_string();
+        assert!(serialized.contains(
+            " pkg.hash=gzip:sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        ));
+        assert!(!serialized.contains("pkg.content-hash"));
+        assert_eq!(Manifest::parse_string(serialized).unwrap(), manifest);
+    }
+
+    #[test]
+    fn verify_payload_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("abc");
+        std::fs::write(&path, b"abc").unwrap();
+
+        let payload = Payload {
+            primary_identifier: Digest::from_str("a9993e364706816aba3e25717850c26c9cd0d89d")
+                .unwrap(),
+            additional_identifiers: vec![Digest::from_str(
+                "file:sha512t_256:53048e2681941ef99b2e29b76b4c7dabe4c2d0c634fc6d46e0e2f13107e7af23",
+            )
+            .unwrap()],
+            ..Payload::default()
+        };
+        let res = payload.verify_file(&path);
+        assert!(res.is_ok(), "error verifying payload: {:?}", res);
+        assert!(res.unwrap());
+
+        std::fs::write(&path, b"abd").unwrap();
+        match payload.verify_file(&path) {
+            Err(PayloadError::DigestMismatch {
+                digest_source,
+                expected,
+                ..
+            }) => {
+                assert_eq!(digest_source, DigestSource::PrimaryPayloadHash);
+                assert_eq!(expected, "a9993e364706816aba3e25717850c26c9cd0d89d");
+            }
+            res => panic!("expected a digest mismatch, got {:?}", res),
+        }
+
+        assert!(payload.verify_file(&dir.path().join("missing")).is_err());
+        assert!(!Payload::default().verify_file(&path).unwrap());
+    }
+
+    #[test]
+    fn fmri_same_release() {
+        let installed =
+            Fmri::parse("pkg://openindiana.org/web/server/nginx@1.18.0,5.11-2020.0.1.0:20200421T195136Z")
+                .unwrap();
+        let republished =
+            Fmri::parse("pkg:/web/server/nginx@1.18.0,5.11-2020.0.1.0:20200512T080000Z").unwrap();
+        let upgrade =
+            Fmri::parse("pkg:/web/server/nginx@1.18.0,5.11-2020.0.1.1:20200512T080000Z").unwrap();
+        let other = Fmri::parse("pkg:/web/server/apache@1.18.0,5.11-2020.0.1.0").unwrap();
+
+        assert!(installed.same_release(&republished));
+        assert!(republished.is_newer_than(&installed));
+        assert!(!installed.same_release(&upgrade));
+        assert!(!installed.same_release(&other));
+        assert!(!installed.same_release(&Fmri::new("web/server/nginx").unwrap()));
+    }
+
+    #[test]
+    fn verify_image() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("usr/bin")).unwrap();
+        std::fs::set_permissions(root.join("usr/bin"), std::fs::Permissions::from_mode(0o755))
+            .unwrap();
+        for (name, mode) in [("hello", 0o555), ("changed", 0o644)] {
+            std::fs::write(root.join("usr/bin").join(name), b"abc").unwrap();
+            std::fs::set_permissions(
+                root.join("usr/bin").join(name),
+                std::fs::Permissions::from_mode(mode),
+            )
+            .unwrap();
+        }
+        symlink("hello", root.join("usr/bin/hi")).unwrap();
+        symlink("gone", root.join("usr/bin/dangling")).unwrap();
+
+        let manifest = Manifest::parse_string(String::from(
+            "dir path=usr/bin mode=0755
+file a9993e364706816aba3e25717850c26c9cd0d89d path=usr/bin/hello mode=0555
+file a9993e364706816aba3e25717850c26c9cd0d89d path=usr/bin/changed mode=0644
+file a9993e364706816aba3e25717850c26c9cd0d89d path=usr/bin/missing mode=0555
+link path=usr/bin/hi target=hello
+link path=usr/bin/dangling target=gone",
+        ))
+        .unwrap();
+        let fmri = Fmri::parse("pkg:/example@1.0.0").unwrap();
+        let image = Image::with_installed_db(root, Arc::new(MemoryInstalledDb::default()));
+        image.installed_db().add(&fmri, &manifest, true).unwrap();
+
+        let res = image.verify();
+        assert!(res.is_ok(), "error verifying image: {:?}", res);
+        assert_eq!(res.unwrap().len(), 2);
+
+        std::fs::set_permissions(
+            root.join("usr/bin/hello"),
+            std::fs::Permissions::from_mode(0o644),
+        )
+        .unwrap();
+        std::fs::write(root.join("usr/bin/changed"), b"abd").unwrap();
+
+        let issues = image.verify().unwrap();
+        let problems: Vec<(&str, &VerifyProblem)> = issues
+            .iter()
+            .map(|i| (i.path.as_str(), &i.problem))
+            .collect();
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert_eq!(
+            problems[0],
+            (
+                "usr/bin/hello",
+                &VerifyProblem::Mode {
+                    expected: 0o555,
+                    actual: 0o644
+                }
+            )
+        );
+        assert!(matches!(problems[1], ("usr/bin/changed", VerifyProblem::Content(_))));
+        assert_eq!(problems[2], ("usr/bin/missing", &VerifyProblem::Missing));
+        assert_eq!(
+            problems[3],
+            (
+                "usr/bin/dangling",
+                &VerifyProblem::DanglingLink {
+                    target: "gone".to_string()
+                }
+            )
+        );
+        assert_eq!(
+            issues[0].to_string(),
+            "pkg:/example@1.0.0 usr/bin/hello: mode is 0644, expected 0555"
+        );
+    }
+
+    #[test]
+    fn installed_package_state() {
+        let fmri = Fmri::parse("pkg://openindiana.org/editor/vim@9.0.0,5.11-2022.0.0.1").unwrap();
+        let image = Image::with_installed_db(
+            "/nonexistent/image",
+            Arc::new(MemoryInstalledDb::default()),
+        );
+        assert_eq!(image.installed_version("editor/vim").unwrap(), None);
+        assert!(image.installed_packages().unwrap().is_empty());
+
+        image.installed_db().add(&fmri, &Manifest::new(), true).unwrap();
+
+        let res = image.installed_version("editor/vim");
+        assert!(res.is_ok(), "error querying installed version: {:?}", res);
+        assert_eq!(res.unwrap(), Some(fmri.clone()));
+        assert_eq!(image.installed_packages().unwrap(), vec![fmri]);
+        assert_eq!(image.installed_version("editor/emacs").unwrap(), None);
+    }
+
+    #[test]
+    fn manually_installed_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = Image::new(dir.path());
+        let vim = Fmri::parse("pkg://openindiana.org/editor/vim@9.0.0").unwrap();
+        let gpm = Fmri::parse("pkg://openindiana.org/system/library/gpm@1.20.7").unwrap();
+
+        image.installed_db().add(&vim, &Manifest::new(), true).unwrap();
+        image.installed_db().add(&gpm, &Manifest::new(), false).unwrap();
+
+        let res = image.is_manually_installed("editor/vim");
+        assert!(res.is_ok(), "error querying install reason: {:?}", res);
+        assert!(res.unwrap());
+        assert!(!image.is_manually_installed("system/library/gpm").unwrap());
+        assert!(!image.is_manually_installed("editor/emacs").unwrap());
+    }
+
+    #[test]
+    fn image_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = Image::new(dir.path());
+        assert!(image.history(None).unwrap().is_empty());
+
+        let vim = Fmri::parse("pkg://openindiana.org/editor/vim@9.0.0").unwrap();
+        let install = HistoryEntry::new("install", vec![vim.clone()], HistoryOutcome::Succeeded);
+        let uninstall = HistoryEntry::new(
+            "uninstall",
+            vec![vim],
+            HistoryOutcome::Failed(String::from("package is in use")),
+        );
+        image.record_history(&install).unwrap();
+        image.record_history(&uninstall).unwrap();
+
+        let res = image.history(None);
+        assert!(res.is_ok(), "error reading history: {:?}", res);
+        assert_eq!(res.unwrap(), vec![uninstall.clone(), install]);
+        assert_eq!(image.history(Some(1)).unwrap(), vec![uninstall]);
+    }
+
+    #[test]
+    fn manifest_classifications() {
+        let manifest = Manifest::parse_string(String::from(
+            "set name=pkg.fmri value=pkg://openindiana.org/web/server/nginx@1.18.0,5.11-2020.0.1.0:20200421T195136Z
+set name=info.classification value=\"org.opensolaris.category.2008:Web Services/Application and Web Servers\"",
+        ));
+        assert!(manifest.is_ok(), "error parsing manifest: {:?}", manifest);
+
+        assert_eq!(
+            manifest.unwrap().classifications(),
+            vec![vec![
+                String::from("Web Services"),
+                String::from("Application and Web Servers")
+            ]]
+        );
+    }
+
+    #[test]
+    fn transform_without_match_keeps_manifest() {
+        let original = Manifest::parse_string(String::from(
+            "set name=variant.arch value=i386 value=sparc
+set name=pkg.description value=\"SPARC kernel\" variant.arch=sparc
+dir group=sys mode=0755 owner=root path=kernel/sparcv9 variant.arch=sparc
+file group=bin mode=0644 overlay=allow owner=root path=etc/nginx/mime.types preserve=renamenew
+depend fmri=web/server/nginx fmri=web/server/nginx-local type=require-any
+link path=usr/bin/nginx target=../sbin/nginx variant.arch=i386 variant.arch=sparc",
+        ))
+        .unwrap();
+
+        let mut manifest = original.clone();
+        let res = Transform::parse("<transform file path=nomatch -> drop>")
+            .unwrap()
+            .apply(&mut manifest);
+        assert!(res.is_ok(), "error applying transform: {:?}", res);
+
+        assert_eq!(manifest, original);
+        assert_eq!(manifest.to_manifest_string(), original.to_manifest_string());
+    }
+
+    #[test]
+    fn canonical_bytes_include_all_attributes() {
+        let parse = |content: &str| Manifest::parse_string(String::from(content)).unwrap();
+
+        let i386 = parse("dir group=sys mode=0755 owner=root path=kernel/drv variant.arch=i386");
+        let sparc = parse("dir group=sys mode=0755 owner=root path=kernel/drv variant.arch=sparc");
+        assert_ne!(i386.canonical_bytes(), sparc.canonical_bytes());
+
+        let both = parse("link path=usr/bin/vi target=vim variant.arch=i386 variant.arch=sparc");
+        let one = parse("link path=usr/bin/vi target=vim variant.arch=sparc");
+        assert_ne!(both.canonical_bytes(), one.canonical_bytes());
+    }
+
+    #[test]
+    fn parse_invalid_hash_values() {
+        let manifest = Manifest::parse_string(String::from(
+            "file hash=md5:abc path=usr/bin/a owner=root group=bin mode=0555
+file md5:abc chash=md5:def path=usr/bin/b owner=root group=bin mode=0555",
+        ));
+        assert!(manifest.is_ok(), "error during Manifest parsing: {:?}", manifest);
+        let manifest = manifest.unwrap();
+
+        assert_eq!(manifest.files[0].payload, None);
+        assert_eq!(
+            manifest.files[0].properties,
+            vec![Property {
+                key: "hash".to_string(),
+                value: "md5:abc".to_string(),
+            }]
+        );
+        assert_eq!(manifest.files[1].payload, None);
+        assert_eq!(
+            manifest.files[1].get_original_path(),
+            Some("md5:abc".to_string())
+        );
+        assert!(manifest.files[1].properties.contains(&Property {
+            key: "chash".to_string(),
+            value: "md5:def".to_string(),
+        }));
+
+        // A content hash without its source is kept as written
+        let manifest = Manifest::parse_string(String::from(
+            "file 4b76e83bb4bb7c87176b72ef805fe78ecae60d2c path=usr/bin/c pkg.content-hash=sha256:ba78",
+        ))
+        .unwrap();
+        assert_eq!(
+            manifest.files[0].payload.as_ref().unwrap().additional_identifiers,
+            vec![]
+        );
+        assert_eq!(
+            manifest.files[0].properties,
+            vec![Property {
+                key: "pkg.content-hash".to_string(),
+                value: "sha256:ba78".to_string(),
+            }]
+        );
+        let reparsed = Manifest::parse_string(manifest.to_manifest_string()).unwrap();
+        assert_eq!(reparsed, manifest);
+        assert!(manifest
+            .to_manifest_string()
+            .contains(" pkg.content-hash=sha256:ba78"));
+    }
+
+    #[test]
+    fn chash_is_compressed_payload_hash() {
+        let manifest = Manifest::parse_string(String::from(
+            "file 4b76e83bb4bb7c87176b72ef805fe78ecae60d2c chash=7288afc78233791bb8e13b3e13aa4f0b4b1d6ee8 path=lib/svc/method/http-nginx pkg.content-hash=gzip:sha512t_256:ec144533fa077af1d5b152d8c7549f113902021d71808adb12ea3f92bda9fd66",
+        ))
+        .unwrap();
+
+        let payload = manifest.files[0].payload.as_ref().unwrap();
+        assert_eq!(payload.additional_identifiers[0].source, DigestSource::GzipCompressed);
+        assert_eq!(payload.additional_identifiers[0].algorithm, DigestAlgorithm::SHA1);
+
+        let act = Action::from(manifest.files[0].clone());
+        assert_eq!(
+            act.get_values("chash"),
+            vec!["7288afc78233791bb8e13b3e13aa4f0b4b1d6ee8"]
+        );
+        assert_eq!(
+            act.get_values("pkg.content-hash"),
+            vec!["gzip:sha512t_256:ec144533fa077af1d5b152d8c7549f113902021d71808adb12ea3f92bda9fd66"]
+        );
+    }
+
+    #[test]
+    fn parse_license_with_invalid_hashes() {
+        let manifest = Manifest::parse_string(String::from(
+            "license md5:0e2b7e5a chash=md5:b4a2c5a4 pkg.content-hash=gzip:md5:ec144533 license=MIT",
+        ));
+        assert!(manifest.is_ok(), "error during Manifest parsing: {:?}", manifest);
+        let license = &manifest.unwrap().licenses[0];
+
+        assert_eq!(license.payload, None);
+        assert_eq!(license.license, "MIT");
+        let keys: Vec<&str> = license.properties.iter().map(|p| p.key.as_str()).collect();
+        assert_eq!(keys, vec!["original-path", "chash", "pkg.content-hash"]);
+    }
+
+    #[test]
+    fn verify_image_owner_names() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("etc")).unwrap();
+        std::fs::create_dir_all(root.join("usr/bin")).unwrap();
+        std::fs::write(root.join("usr/bin/mine"), b"abc").unwrap();
+        std::fs::write(root.join("usr/bin/theirs"), b"abc").unwrap();
+        let meta = std::fs::metadata(root.join("usr/bin/mine")).unwrap();
+        let (uid, gid) = (meta.uid(), meta.gid());
+
+        std::fs::write(
+            root.join("etc/passwd"),
+            format!(
+                "me:x:{}:{}::/:/bin/sh\nwebservd:x:{}:{}::/:/bin/sh\n",
+                uid,
+                gid,
+                uid + 1,
+                gid
+            ),
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("etc/group"),
+            format!("mine::{}:\nwebservd::{}:\n", gid, gid + 1),
+        )
+        .unwrap();
+
+        let manifest = Manifest::parse_string(String::from(
+            "file path=usr/bin/mine owner=me group=mine
+file path=usr/bin/theirs owner=webservd group=webservd
+dir path=usr/bin owner=nosuchuser group=nosuchgroup",
+        ))
+        .unwrap();
+        let fmri = Fmri::parse("pkg:/example@1.0.0").unwrap();
+        let image = Image::with_installed_db(root, Arc::new(MemoryInstalledDb::default()));
+        image.installed_db().add(&fmri, &manifest, true).unwrap();
+
+        let res = image.verify();
+        assert!(res.is_ok(), "error verifying image: {:?}", res);
+        let issues = res.unwrap();
+        let problems: Vec<(&str, &VerifyProblem)> = issues
+            .iter()
+            .map(|i| (i.path.as_str(), &i.problem))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                ("usr/bin", &VerifyProblem::UnknownOwner("nosuchuser".to_string())),
+                ("usr/bin", &VerifyProblem::UnknownGroup("nosuchgroup".to_string())),
+                (
+                    "usr/bin/theirs",
+                    &VerifyProblem::Owner {
+                        expected: uid + 1,
+                        actual: uid
+                    }
+                ),
+                (
+                    "usr/bin/theirs",
+                    &VerifyProblem::Group {
+                        expected: gid + 1,
+                        actual: gid
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reconcile_manifests_with_transforms() {
+        let old = Manifest::parse_string(String::from(
+            "set name=pkg.fmri value=pkg://openindiana.org/web/server/nginx@1.18.0,5.11-2020.0.1.0
+set name=pkg.summary value=\"Nginx Webserver\"
+set name=info.classification value=org.opensolaris.category.2008:Web value=legacy
+dir path=usr/share/doc/nginx owner=root group=bin mode=0755
+file 4b76e83bb4bb7c87176b72ef805fe78ecae60d2c path=usr/bin/nginx owner=root group=bin mode=0755
+file ab5eadc1d0d8b7a4e8a52c1a5b30e2fb8b3a25d4 path=usr/share/man/man8/nginx.8 owner=root group=bin mode=0644
+file 2d5b4ffb7a0a2d8f0f1c8f6a1d0d3e2b63f3d1a7 path=kernel/drv/nginx owner=root group=sys mode=0755 variant.arch=i386
+file 9c9b9b6a8a7d6a7fdbd5a4d5d1e0b3c3f2e2f1f0 path=kernel/drv/nginx owner=root group=sys mode=0755 variant.arch=sparc
+depend fmri=library/pcre type=require",
+        ))
+        .unwrap();
+        let new = Manifest::parse_string(String::from(
+            "set name=pkg.fmri value=pkg://openindiana.org/web/server/nginx@1.20.1,5.11-2021.0.0.0
+set name=pkg.summary value=\"Nginx Webserver\"
+set name=info.classification value=org.opensolaris.category.2008:Web
+file 4b76e83bb4bb7c87176b72ef805fe78ecae60d2c path=usr/bin/nginx owner=root group=bin mode=0555 restart_fmri=svc:/network/http:nginx
+file 0f1e2d3c4b5a69788796a5b4c3d2e1f00f1e2d3c path=usr/share/man/man8/nginx.8 owner=root group=bin mode=0644
+file 2d5b4ffb7a0a2d8f0f1c8f6a1d0d3e2b63f3d1a7 path=kernel/drv/nginx owner=root group=sys mode=0755 variant.arch=i386
+link path=usr/sbin/nginx target=../bin/nginx
+depend fmri=library/pcre2 type=require",
+        ))
+        .unwrap();
+
+        let rules = reconcile(&old, &new);
+        assert!(rules.is_ok(), "error reconciling manifests: {:?}", rules);
+        let rules = rules.unwrap();
+        assert!(rules.contains(&String::from(
+            "<transform file path=usr/bin/nginx -> set mode 0555>"
+        )));
+        assert!(rules.contains(&String::from(
+            "<transform dir path=usr/share/doc/nginx -> drop>"
+        )));
+
+        let transforms: Vec<Transform> = rules
+            .iter()
+            .map(|rule| Transform::parse(rule).unwrap())
+            .collect();
+        let mut reconciled = old.clone();
+        let res = apply_all(&transforms, &mut reconciled);
+        assert!(res.is_ok(), "error applying transforms: {:?}", res);
+        assert_eq!(
+            String::from_utf8(reconciled.canonical_bytes()).unwrap(),
+            String::from_utf8(new.canonical_bytes()).unwrap()
+        );
+
+        assert_eq!(reconcile(&old, &old).unwrap(), Vec::<String>::new());
+
+        // Without any action to hang them off new actions cannot be emitted
+        let added = Manifest::parse_string(String::from("dir path=a")).unwrap();
+        assert!(matches!(
+            reconcile(&Manifest::new(), &added),
+            Err(TransformError::NoEmitAnchor { .. })
+        ));
+    }
 }
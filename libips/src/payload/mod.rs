@@ -20,6 +20,12 @@ pub enum PayloadError {
     IOError(#[from] IOError),
     #[error("digest error: {0}")]
     DigestError(#[from] DigestError),
+    #[error("{digest_source} digest mismatch: expected {expected} but file has {actual}")]
+    DigestMismatch {
+        digest_source: DigestSource,
+        expected: String,
+        actual: String,
+    },
 }
 
 #[derive(Debug, PartialEq, Clone, Default, Deserialize, Serialize, Diff)]
@@ -63,6 +69,10 @@ pub enum PayloadArchitecture {
 pub struct Payload {
     pub primary_identifier: Digest,
     pub additional_identifiers: Vec<Digest>,
+    // The additional identifiers which were given as pkg.hash instead of
+    // pkg.content-hash, so they are written back under the same attribute
+    #[serde(default)]
+    pub pkg_hash_identifiers: Vec<Digest>,
     pub compression_algorithm: PayloadCompressionAlgorithm,
     pub bitness: PayloadBits,
     pub architecture: PayloadArchitecture,
@@ -73,6 +83,33 @@ impl Payload {
         self.architecture == PayloadArchitecture::NOARCH && self.bitness == PayloadBits::Independent
     }
 
+    // Check the file at path against the primary identifier and the
+    // uncompressed file digests. Returns false if there is no primary
+    // identifier to verify against.
+    pub fn verify_file(&self, path: &Path) -> Result<bool> {
+        if self.primary_identifier.hash.is_empty() {
+            return Ok(false);
+        }
+
+        let b = std::fs::read(path)?;
+        let expected = std::iter::once(&self.primary_identifier).chain(
+            self.additional_identifiers
+                .iter()
+                .filter(|d| d.source == DigestSource::UncompressedFile),
+        );
+        for digest in expected {
+            let actual = Digest::from_bytes(&b, digest.algorithm.clone(), digest.source.clone())?;
+            if actual.hash != digest.hash {
+                return Err(PayloadError::DigestMismatch {
+                    digest_source: digest.source.clone(),
+                    expected: digest.hash.clone(),
+                    actual: actual.hash,
+                });
+            }
+        }
+        Ok(true)
+    }
+
     pub fn compute_payload(path: &Path) -> Result<Self> {
         let f = std::fs::read(path)?;
 
@@ -106,6 +143,7 @@ impl Payload {
                 DigestSource::PrimaryPayloadHash,
             )?,
             additional_identifiers: Vec::<Digest>::new(),
+            pkg_hash_identifiers: Vec::<Digest>::new(),
             compression_algorithm: PayloadCompressionAlgorithm::default(),
             bitness,
             architecture,
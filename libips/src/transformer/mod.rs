@@ -0,0 +1,483 @@
+//  This Source Code Form is subject to the terms of
+//  the Mozilla Public License, v. 2.0. If a copy of the
+//  MPL was not distributed with this file, You can
+//  obtain one at https://mozilla.org/MPL/2.0/.
+
+// Source https://docs.oracle.com/cd/E26502_01/html/E21383/pkgmogrify-1.html
+
+use crate::actions::{Action, ActionError, Manifest, Property};
+use diff::Diff;
+use regex::Regex;
+use std::collections::HashMap;
+use std::result::Result as StdResult;
+use thiserror::Error;
+
+type Result<T> = StdResult<T, TransformError>;
+
+#[derive(Debug, Error)]
+pub enum TransformError {
+    #[error("transform rule {rule:?} is invalid: {details}")]
+    InvalidRule { rule: String, details: String },
+
+    #[error(transparent)]
+    RegexError(#[from] regex::Error),
+
+    #[error("transform aborted on action: {action}")]
+    Aborted { action: String },
+
+    #[error(transparent)]
+    ActionError(#[from] ActionError),
+
+    #[error("no transform can select only the action: {action}")]
+    AmbiguousAction { action: String },
+
+    #[error("cannot emit {action}: no action occurs exactly once in the old manifest")]
+    NoEmitAnchor { action: String },
+}
+
+#[derive(Debug, Clone)]
+pub enum Operation {
+    // add <attribute> <value>, append a value
+    Add {
+        attribute: String,
+        value: String,
+    },
+    // set <attribute> <value>, replace all values
+    Set {
+        attribute: String,
+        value: String,
+    },
+    // delete <attribute> <regex>, remove matching values
+    Delete {
+        attribute: String,
+        pattern: Regex,
+    },
+    // drop, remove the action from the manifest
+    Drop,
+    // edit <attribute> <regex> <replacement>
+    Edit {
+        attribute: String,
+        pattern: Regex,
+        replacement: String,
+    },
+    // abort, fail when a forbidden action is present
+    Abort,
+    // emit <action>, add an action to the manifest
+    Emit { action: Action },
+}
+
+// A pkgmogrify style rule: <transform [action types] [attr=regex ...] -> operation>
+#[derive(Debug, Clone)]
+pub struct Transform {
+    pub action_types: Vec<String>,
+    pub matchers: Vec<(String, Regex)>,
+    pub operation: Operation,
+}
+
+impl Transform {
+    pub fn parse(rule: &str) -> Result<Transform> {
+        let invalid = |details: &str| TransformError::InvalidRule {
+            rule: rule.to_owned(),
+            details: details.to_owned(),
+        };
+
+        let inner = rule
+            .trim()
+            .strip_prefix("<transform")
+            .and_then(|r| r.strip_suffix('>'))
+            .ok_or_else(|| invalid("rule must be enclosed in <transform ... >"))?;
+        let (selector, operation) = inner
+            .split_once("->")
+            .ok_or_else(|| invalid("missing -> between matchers and operation"))?;
+
+        let mut action_types = vec![];
+        let mut matchers = vec![];
+        for token in split_words(selector).map_err(|e| invalid(&e))? {
+            match token.split_once('=') {
+                Some((attribute, pattern)) => matchers.push((
+                    attribute.to_owned(),
+                    Regex::new(&format!("^(?:{})$", pattern))?,
+                )),
+                None => action_types.push(token),
+            }
+        }
+
+        if let Some(line) = operation.trim().strip_prefix("emit ") {
+            let mut actions = Manifest::parse_string(line.trim().to_owned())?.actions();
+            if actions.len() != 1 {
+                return Err(invalid("emit requires exactly one action"));
+            }
+            return Ok(Transform {
+                action_types,
+                matchers,
+                operation: Operation::Emit {
+                    action: actions.remove(0),
+                },
+            });
+        }
+
+        let words = split_words(operation).map_err(|e| invalid(&e))?;
+        let operation = match words.first().map(String::as_str) {
+            Some(op @ ("add" | "set")) => {
+                if words.len() != 3 {
+                    return Err(invalid(&format!(
+                        "{} requires an attribute and a value",
+                        op
+                    )));
+                }
+                let (attribute, value) = (words[1].clone(), words[2].clone());
+                if op == "add" {
+                    Operation::Add { attribute, value }
+                } else {
+                    Operation::Set { attribute, value }
+                }
+            }
+            Some("delete") => {
+                if words.len() != 3 {
+                    return Err(invalid("delete requires an attribute and a regex"));
+                }
+                Operation::Delete {
+                    attribute: words[1].clone(),
+                    pattern: Regex::new(&words[2])?,
+                }
+            }
+            Some("drop") => {
+                if words.len() != 1 {
+                    return Err(invalid("drop takes no arguments"));
+                }
+                Operation::Drop
+            }
+            Some("edit") => {
+                if words.len() != 4 {
+                    return Err(invalid(
+                        "edit requires an attribute, a regex and a replacement",
+                    ));
+                }
+                Operation::Edit {
+                    attribute: words[1].clone(),
+                    pattern: Regex::new(&words[2])?,
+                    replacement: backrefs_to_regex_syntax(&words[3]),
+                }
+            }
+            Some("abort") => {
+                if words.len() != 1 {
+                    return Err(invalid("abort takes no arguments"));
+                }
+                Operation::Abort
+            }
+            Some(op) => return Err(invalid(&format!("unknown operation {}", op))),
+            None => return Err(invalid("missing operation")),
+        };
+
+        Ok(Transform {
+            action_types,
+            matchers,
+            operation,
+        })
+    }
+
+    fn matches(&self, act: &Action) -> bool {
+        if !self.action_types.is_empty() && !self.action_types.iter().any(|t| t == act.name()) {
+            return false;
+        }
+        self.matchers.iter().all(|(attribute, pattern)| {
+            act.get_values(attribute)
+                .iter()
+                .any(|value| pattern.is_match(value))
+        })
+    }
+
+    // Returns false if the action has to be dropped
+    fn apply_to_action(&self, act: &mut Action) -> Result<bool> {
+        match &self.operation {
+            Operation::Add { attribute, value } => act.properties.push(Property {
+                key: attribute.clone(),
+                value: value.clone(),
+            }),
+            Operation::Set { attribute, value } => {
+                act.properties.retain(|prop| &prop.key != attribute);
+                act.properties.push(Property {
+                    key: attribute.clone(),
+                    value: value.clone(),
+                });
+            }
+            Operation::Delete { attribute, pattern } => act
+                .properties
+                .retain(|prop| &prop.key != attribute || !pattern.is_match(&prop.value)),
+            Operation::Drop => return Ok(false),
+            Operation::Edit {
+                attribute,
+                pattern,
+                replacement,
+            } => {
+                for prop in act.properties.iter_mut() {
+                    if &prop.key == attribute {
+                        prop.value = pattern
+                            .replace_all(&prop.value, replacement.as_str())
+                            .into_owned();
+                    }
+                }
+            }
+            Operation::Abort => {
+                return Err(TransformError::Aborted {
+                    action: act.to_string(),
+                })
+            }
+            // Handled in apply_all as the action itself is kept
+            Operation::Emit { .. } => {}
+        }
+        Ok(true)
+    }
+
+    pub fn apply(&self, manifest: &mut Manifest) -> Result<()> {
+        apply_all(std::slice::from_ref(self), manifest)
+    }
+}
+
+// Apply the transforms in order to every action of the manifest.
+pub fn apply_all(transforms: &[Transform], manifest: &mut Manifest) -> Result<()> {
+    let mut transformed = Manifest::new();
+    'actions: for mut act in manifest.actions() {
+        for transform in transforms {
+            if !transform.matches(&act) {
+                continue;
+            }
+            if let Operation::Emit { action } = &transform.operation {
+                transformed.add_action(action.clone());
+            }
+            if !transform.apply_to_action(&mut act)? {
+                continue 'actions;
+            }
+        }
+        transformed.add_action(act);
+    }
+    *manifest = transformed;
+    Ok(())
+}
+
+// The transform rules which turn the old manifest into the new one. Actions
+// present in both are edited with set and delete, removed ones dropped and
+// added ones emitted. Actions whose payload changed are dropped and emitted
+// again as transforms cannot change payloads.
+pub fn reconcile(old: &Manifest, new: &Manifest) -> Result<Vec<String>> {
+    if old.diff(new) == old.diff(old) {
+        return Ok(vec![]);
+    }
+
+    let old_actions = old.actions();
+    let new_actions = new.actions();
+    let old_ids = group_by_identity(&old_actions);
+    let new_ids = group_by_identity(&new_actions);
+
+    let mut edits = vec![];
+    let mut emitted: Vec<&Action> = vec![];
+    for act in &old_actions {
+        let id = identity(act);
+        let replacement = match (old_ids[&id].as_slice(), new_ids.get(&id).map(Vec::as_slice)) {
+            ([_], Some([new_act])) if act.payload_string == new_act.payload_string => {
+                let selector = selector(act, &old_actions)?;
+                for op in property_changes(act, new_act) {
+                    edits.push(format!("<transform {} -> {}>", selector, op));
+                }
+                continue;
+            }
+            (_, Some(new_group)) => new_group.to_vec(),
+            (_, None) => vec![],
+        };
+        if same_actions(&old_ids[&id], &replacement) {
+            continue;
+        }
+        edits.push(format!("<transform {} -> drop>", selector(act, &old_actions)?));
+        // Emit the replacements only once for the whole group
+        if std::ptr::eq(old_ids[&id][0], act) {
+            emitted.extend(replacement);
+        }
+    }
+    emitted.extend(
+        new_actions
+            .iter()
+            .filter(|act| !old_ids.contains_key(&identity(act))),
+    );
+
+    // Emitted actions hang off an action which exists exactly once. They are
+    // emitted before any edit so that action may still be dropped.
+    let mut rules = vec![];
+    if !emitted.is_empty() {
+        let anchor = old_actions
+            .iter()
+            .find_map(|act| selector(act, &old_actions).ok())
+            .ok_or_else(|| TransformError::NoEmitAnchor {
+                action: emitted[0].to_string(),
+            })?;
+        for act in emitted {
+            rules.push(format!("<transform {} -> emit {}>", anchor, act));
+        }
+    }
+    rules.extend(edits);
+    Ok(rules)
+}
+
+// The attributes which identify an action of the given type in a manifest
+fn key_attributes(name: &str) -> &'static [&'static str] {
+    match name {
+        "set" | "driver" => &["name"],
+        "dir" | "file" | "link" | "hardlink" => &["path"],
+        "depend" => &["type", "fmri"],
+        "license" => &["license"],
+        "user" => &["username"],
+        "group" => &["groupname"],
+        "legacy" => &["pkg"],
+        _ => &[],
+    }
+}
+
+fn identity(act: &Action) -> String {
+    let keys = key_attributes(act.name());
+    let mut id: Vec<String> = act
+        .properties
+        .iter()
+        .filter(|prop| keys.contains(&prop.key.as_str()) || prop.key.starts_with("variant."))
+        .map(|prop| format!("{}={}", prop.key, prop.value))
+        .collect();
+    id.sort();
+    format!("{} {}", act.name(), id.join(" "))
+}
+
+fn group_by_identity(actions: &[Action]) -> HashMap<String, Vec<&Action>> {
+    let mut groups: HashMap<String, Vec<&Action>> = HashMap::new();
+    for act in actions {
+        groups.entry(identity(act)).or_default().push(act);
+    }
+    groups
+}
+
+fn sorted_line(act: &Action) -> String {
+    let mut act = act.clone();
+    act.properties
+        .sort_by(|a, b| a.key.cmp(&b.key).then_with(|| a.value.cmp(&b.value)));
+    act.to_string()
+}
+
+fn same_actions(a: &[&Action], b: &[&Action]) -> bool {
+    let mut a: Vec<String> = a.iter().map(|act| sorted_line(act)).collect();
+    let mut b: Vec<String> = b.iter().map(|act| sorted_line(act)).collect();
+    a.sort();
+    b.sort();
+    a == b
+}
+
+// The selector part of a rule matching only this action. The identifying
+// attributes are tried first, all attributes if they match other actions.
+fn selector(act: &Action, actions: &[Action]) -> Result<String> {
+    let keys = key_attributes(act.name());
+    let by_identity: Vec<&Property> = act
+        .properties
+        .iter()
+        .filter(|prop| keys.contains(&prop.key.as_str()) || prop.key.starts_with("variant."))
+        .collect();
+    let all: Vec<&Property> = act.properties.iter().collect();
+
+    for props in [by_identity, all] {
+        let mut selector = act.name().to_owned();
+        for prop in props {
+            selector.push_str(&format!(
+                " {}",
+                quote_word(&format!("{}={}", prop.key, regex::escape(&prop.value)))
+            ));
+        }
+        let transform = Transform::parse(&format!("<transform {} -> drop>", selector))?;
+        if actions.iter().filter(|a| transform.matches(a)).count() == 1 {
+            return Ok(selector);
+        }
+    }
+    Err(TransformError::AmbiguousAction {
+        action: act.to_string(),
+    })
+}
+
+// The set and delete operations changing the attributes of old to the ones of new
+fn property_changes(old: &Action, new: &Action) -> Vec<String> {
+    let mut keys: Vec<&str> = old
+        .properties
+        .iter()
+        .chain(new.properties.iter())
+        .map(|prop| prop.key.as_str())
+        .collect();
+    keys.sort_unstable();
+    keys.dedup();
+
+    let mut ops = vec![];
+    for key in keys {
+        let mut old_values = old.get_values(key);
+        let mut new_values = new.get_values(key);
+        old_values.sort_unstable();
+        new_values.sort_unstable();
+        if old_values == new_values {
+            continue;
+        }
+        match new_values.split_first() {
+            None => ops.push(format!("delete {} .*", quote_word(key))),
+            Some((first, rest)) => {
+                ops.push(format!("set {} {}", quote_word(key), quote_word(first)));
+                for value in rest {
+                    ops.push(format!("add {} {}", quote_word(key), quote_word(value)));
+                }
+            }
+        }
+    }
+    ops
+}
+
+// Quote a word for split_words if it would otherwise be split
+fn quote_word(word: &str) -> String {
+    if !word.is_empty() && !word.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'') {
+        word.to_owned()
+    } else if word.contains('"') {
+        format!("'{}'", word)
+    } else {
+        format!("\"{}\"", word)
+    }
+}
+
+// Split on whitespace while keeping single or double quoted words together
+fn split_words(s: &str) -> StdResult<Vec<String>, String> {
+    let mut words = vec![];
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+
+    for c in s.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => current.push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                in_word = true;
+            }
+            None if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            None => {
+                current.push(c);
+                in_word = true;
+            }
+        }
+    }
+
+    if quote.is_some() {
+        return Err(String::from("unterminated quote"));
+    }
+    if in_word {
+        words.push(current);
+    }
+    Ok(words)
+}
+
+// pkgmogrify uses \1 for backreferences, the regex crate $1. Accept both.
+fn backrefs_to_regex_syntax(replacement: &str) -> String {
+    let backref = Regex::new(r"\\(\d+)").unwrap();
+    backref.replace_all(replacement, "$${$1}").into_owned()
+}
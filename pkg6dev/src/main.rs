@@ -1,12 +1,14 @@
 use clap::{Parser, Subcommand};
 use libips::actions::{ActionError, File, Manifest};
+use libips::transformer::reconcile;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use std::collections::HashMap;
 use std::fs::{read_dir, OpenOptions};
 use std::io::Write;
 use std::path::{Path, PathBuf};
 use userland::repology::find_newest_version;
+use userland::sources::fetch_verified_archive;
 use userland::{Component, Makefile};
 
 #[derive(Parser, Debug)]
@@ -31,6 +33,22 @@ enum Commands {
     ShowComponent {
         component: String,
     },
+    /// Download the source archive of a component and verify its hash
+    FetchSource {
+        component: String,
+    },
+    /// Print the transforms which turn manifest a into manifest b
+    ManifestDiff {
+        #[clap(short = 'a')]
+        old_manifest: PathBuf,
+
+        #[clap(short = 'b')]
+        new_manifest: PathBuf,
+
+        /// Write the transforms into this file instead of printing them
+        #[clap(long)]
+        emit_transform: Option<PathBuf>,
+    },
 }
 
 fn main() -> Result<()> {
@@ -38,11 +56,17 @@ fn main() -> Result<()> {
 
     match &cli.command {
         Commands::ShowComponent { component } => show_component_info(component),
+        Commands::FetchSource { component } => fetch_source(component),
         Commands::DiffComponent {
             component,
             replacements,
             output_manifest,
         } => diff_component(component, replacements, output_manifest),
+        Commands::ManifestDiff {
+            old_manifest,
+            new_manifest,
+            emit_transform,
+        } => manifest_diff(old_manifest, new_manifest, emit_transform),
     }
 }
 
@@ -151,14 +175,9 @@ fn show_component_info<P: AsRef<Path>>(component_path: P) -> Result<()> {
 
     if let Some(var) = makefile.get("COMPONENT_VERSION") {
         println!("Version: {}", var.replace('\n', "\n\t"));
-        let latest_version = find_newest_version(&name);
-        if latest_version.is_ok() {
-            println!("Latest Version: {}", latest_version?);
-        } else {
-            println!(
-                "Error: Could not get latest version info: {:?}",
-                latest_version.unwrap_err()
-            )
+        match find_newest_version(&name) {
+            Ok(latest_version) => println!("Latest Version: {}", latest_version),
+            Err(e) => println!("Error: Could not get latest version info: {:?}", e),
         }
     }
 
@@ -195,6 +214,60 @@ fn show_component_info<P: AsRef<Path>>(component_path: P) -> Result<()> {
     Ok(())
 }
 
+fn fetch_source<P: AsRef<Path>>(component_path: P) -> Result<()> {
+    let makefile_path = component_path.as_ref().join("Makefile");
+
+    let initial_makefile = Makefile::parse_single_file(makefile_path)?;
+    let makefile = initial_makefile.parse_all()?;
+
+    let url = makefile
+        .get("COMPONENT_ARCHIVE_URL")
+        .ok_or_else(|| anyhow!("component has no COMPONENT_ARCHIVE_URL"))?;
+
+    println!("Fetching {}", url);
+    let data = fetch_verified_archive(&makefile, url.trim())?;
+    println!("Hash verified");
+
+    let archive_name = match makefile.get("COMPONENT_ARCHIVE") {
+        Some(name) => name,
+        None => url.trim().rsplit('/').next().unwrap_or_default().to_owned(),
+    };
+    std::fs::write(component_path.as_ref().join(&archive_name), data)?;
+    println!("Saved {}", archive_name);
+
+    Ok(())
+}
+
+fn manifest_diff(
+    old_manifest: &Path,
+    new_manifest: &Path,
+    emit_transform: &Option<PathBuf>,
+) -> Result<()> {
+    let old = Manifest::parse_file(old_manifest)?;
+    let new = Manifest::parse_file(new_manifest)?;
+
+    let rules = reconcile(&old, &new)?;
+    match emit_transform {
+        Some(path) => {
+            let mut f = OpenOptions::new()
+                .write(true)
+                .truncate(true)
+                .create(true)
+                .open(path)?;
+            for rule in rules {
+                writeln!(&mut f, "{}", rule)?;
+            }
+        }
+        None => {
+            for rule in rules {
+                println!("{}", rule);
+            }
+        }
+    }
+
+    Ok(())
+}
+
 // Show all files that have been removed in the sample-manifest
 fn find_removed_files<P: AsRef<Path>>(
     sample_manifest: &Manifest,
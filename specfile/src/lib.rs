@@ -1,6 +1,6 @@
 pub mod macros;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use pest::Parser;
 use pest_derive::Parser;
 use std::collections::HashMap;
@@ -20,12 +20,120 @@ pub struct SpecFile {
     pub variables: HashMap<String, String>,
     pub description: String,
     pub prep_script: String,
+    pub setup: Option<Setup>,
     pub build_script: String,
     pub install_script: String,
     pub files: Vec<String>,
     pub changelog: String,
 }
 
+// What the %setup directive of the %prep section asks for: the directory
+// the sources get unpacked into and which sources to unpack in order.
+#[derive(Default, Debug, PartialEq)]
+pub struct Setup {
+    pub build_dir: String,
+    pub quiet: bool,
+    pub create_build_dir: bool,
+    pub sources: Vec<u32>,
+}
+
+impl Setup {
+    fn parse(line: &str, spec: &SpecFile) -> Result<Setup> {
+        let mut setup = Setup {
+            build_dir: format!("{}-{}", spec.name, spec.version),
+            ..Setup::default()
+        };
+        let mut unpack_source0 = true;
+        let mut before = vec![];
+        let mut after = vec![];
+
+        let mut args = line.split_whitespace().skip(1);
+        while let Some(arg) = args.next() {
+            match arg {
+                "-q" => setup.quiet = true,
+                "-c" => setup.create_build_dir = true,
+                "-T" => unpack_source0 = false,
+                "-n" => match args.next() {
+                    Some(dir) => setup.build_dir = spec.expand_macros(dir),
+                    None => bail!("%setup -n requires a directory name"),
+                },
+                _ if arg.starts_with("-a") || arg.starts_with("-b") => {
+                    let number = if arg.len() > 2 {
+                        &arg[2..]
+                    } else {
+                        match args.next() {
+                            Some(n) => n,
+                            None => bail!("%setup {} requires a source number", arg),
+                        }
+                    };
+                    let number: u32 = number.parse()?;
+                    if arg.starts_with("-a") {
+                        after.push(number);
+                    } else {
+                        before.push(number);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        setup.sources.append(&mut before);
+        if unpack_source0 {
+            setup.sources.push(0);
+        }
+        setup.sources.append(&mut after);
+
+        Ok(setup)
+    }
+}
+
+impl SpecFile {
+    // Replaces %{macro} and %macro references to the spec's tags, e.g.
+    // %{name}-%{version}. Unknown macros are left as they are.
+    pub fn expand_macros(&self, text: &str) -> String {
+        let lookup = |name: &str| -> Option<&str> {
+            match name {
+                "name" => Some(&self.name),
+                "version" => Some(&self.version),
+                "release" => Some(&self.release),
+                "summary" => Some(&self.summary),
+                "license" => Some(&self.license),
+                _ => self
+                    .variables
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                    .map(|(_, value)| value.as_str()),
+            }
+        };
+
+        let mut expanded = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('%') {
+            expanded.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let (name, len) = match after.strip_prefix('{') {
+                Some(braced) => match braced.find('}') {
+                    Some(end) => (&braced[..end], end + 2),
+                    None => ("", 0),
+                },
+                None => {
+                    let end = after
+                        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                        .unwrap_or(after.len());
+                    (&after[..end], end)
+                }
+            };
+            match lookup(name).filter(|_| !name.is_empty()) {
+                Some(value) => expanded.push_str(value),
+                None => expanded.push_str(&rest[start..start + 1 + len]),
+            }
+            rest = &after[len..];
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+}
+
 enum KnownVariableControl {
     Name,
     Version,
@@ -181,12 +289,32 @@ pub fn parse(file_contents: String) -> Result<SpecFile> {
         }
     }
 
+    // Later %setup lines, usually %setup -T -D -a 1, unpack further sources
+    // into the build directory of the first one.
+    for line in spec
+        .prep_script
+        .lines()
+        .filter(|l| l.trim_start().starts_with("%setup"))
+    {
+        let setup = Setup::parse(line, &spec)?;
+        match spec.setup.as_mut() {
+            Some(first) => {
+                for source in setup.sources {
+                    if !first.sources.contains(&source) {
+                        first.sources.push(source);
+                    }
+                }
+            }
+            None => spec.setup = Some(setup),
+        }
+    }
+
     Ok(spec)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::parse;
+    use crate::{parse, Setup};
     use std::fs;
 
     #[test]
@@ -205,4 +333,80 @@ mod tests {
             Err(e) => panic!("io error: {:}", e),
         }
     }
+
+    #[test]
+    fn test_parse_setup() {
+        let spec = parse(String::from(
+            "Name:       foo
+Version:    1.0
+
+%prep
+%setup -q -n foo-1.0
+",
+        ))
+        .unwrap();
+        assert_eq!(
+            spec.setup,
+            Some(Setup {
+                build_dir: "foo-1.0".to_string(),
+                quiet: true,
+                create_build_dir: false,
+                sources: vec![0],
+            })
+        );
+
+        let spec = parse(String::from(
+            "Name:       foo
+Version:    1.0
+
+%prep
+%setup -T -c -a 1
+",
+        ))
+        .unwrap();
+        assert_eq!(
+            spec.setup,
+            Some(Setup {
+                build_dir: "foo-1.0".to_string(),
+                quiet: false,
+                create_build_dir: true,
+                sources: vec![1],
+            })
+        );
+
+        let spec = parse(String::from(
+            "Name:       foo
+Version:    1.0
+
+%prep
+%setup -q -n %{name}-%version
+",
+        ))
+        .unwrap();
+        assert_eq!(spec.setup.unwrap().build_dir, "foo-1.0");
+
+        let spec = parse(String::from(
+            "Name:       foo
+Version:    1.0
+
+%prep
+%setup -q
+%setup -T -D -a 1
+%setup -q -D -a 2
+",
+        ))
+        .unwrap();
+        assert_eq!(
+            spec.setup,
+            Some(Setup {
+                build_dir: "foo-1.0".to_string(),
+                quiet: true,
+                create_build_dir: false,
+                sources: vec![0, 1, 2],
+            })
+        );
+
+        let contents = fs::read_to_string("src/test_data/simple.spec").unwrap();
+        assert_eq!(parse(contents).unwrap().setup, None);
+    }
 }
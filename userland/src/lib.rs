@@ -1,5 +1,6 @@
 mod component;
 pub mod repology;
+pub mod sources;
 
 use anyhow::{anyhow, Context, Result};
 use lazy_static::lazy_static;
@@ -35,16 +36,13 @@ pub struct MakefileVariable {
     pub mode: VariableMode,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Default, PartialEq, Clone)]
 pub enum VariableMode {
     Add,
+    #[default]
     Set,
-}
-
-impl Default for VariableMode {
-    fn default() -> Self {
-        Self::Set
-    }
+    // Assigned with := and thus expanded when the assignment is parsed
+    Immediate,
 }
 
 #[derive(Error, Debug)]
@@ -106,7 +104,7 @@ impl Makefile {
                         self.variables.insert(key, var);
                     }
                 }
-                VariableMode::Set => {
+                VariableMode::Set | VariableMode::Immediate => {
                     self.variables.insert(key, var);
                 }
             }
@@ -172,7 +170,7 @@ impl Makefile {
             for captures in VARRE.captures_iter(maybe_nested_var) {
                 if let Some(nested_var) = captures.name("var_name") {
                     let nested_var_name = nested_var.as_str().replace("$(", "").replace(')', "");
-                    if let Some(resolved_nested_var) = self.get(&nested_var_name) {
+                    if let Some(resolved_nested_var) = self.get_substituted(&nested_var_name) {
                         let mut new_string = vars_copy[i].clone();
                         new_string =
                             new_string.replacen(nested_var.as_str(), &resolved_nested_var, 1);
@@ -184,6 +182,26 @@ impl Makefile {
         vars_copy
     }
 
+    // Resolves a variable reference which may be a substitution reference of
+    // the form VAR:pattern=replacement as in $(SOURCES:.c=.o)
+    fn get_substituted(&self, reference: &str) -> Option<String> {
+        let (var_name, substitution) = match reference.split_once(':') {
+            Some((var_name, substitution)) => (var_name, substitution),
+            None => return self.get(reference),
+        };
+        let value = self.get(var_name)?;
+        let (pattern, replacement) = match substitution.split_once('=') {
+            Some(pair) => pair,
+            None => return Some(value),
+        };
+
+        let words: Vec<String> = value
+            .split_whitespace()
+            .map(|word| substitute_word(word, pattern, replacement))
+            .collect();
+        Some(words.join(" "))
+    }
+
     pub fn get_first_value_of_variable_by_name(&self, var_name: &str) -> Option<String> {
         if let Some(var) = self.variables.get(var_name) {
             let vars_resolved = self.resolve_nested_variables(var);
@@ -194,7 +212,24 @@ impl Makefile {
     }
 }
 
-fn vars_to_string(vars: &Vec<String>) -> String {
+// Same semantics as GNU make: without a % the pattern is a suffix to replace
+fn substitute_word(word: &str, pattern: &str, replacement: &str) -> String {
+    let (pattern, replacement) = if pattern.contains('%') {
+        (pattern.to_owned(), replacement.to_owned())
+    } else {
+        (format!("%{}", pattern), format!("%{}", replacement))
+    };
+    let (prefix, suffix) = pattern.split_once('%').unwrap();
+    match word
+        .strip_prefix(prefix)
+        .and_then(|rest| rest.strip_suffix(suffix))
+    {
+        Some(stem) => replacement.replacen('%', stem, 1),
+        None => word.to_owned(),
+    }
+}
+
+fn vars_to_string(vars: &[String]) -> String {
     if vars.is_empty() {
         String::new()
     } else if vars.len() == 1 {
@@ -292,6 +327,9 @@ fn parse_variable(variable_pair: Pairs<crate::Rule>, m: &mut Makefile) -> Result
             Rule::variable_set => {
                 var.1.mode = VariableMode::Set
             },
+            Rule::variable_immediate => {
+                var.1.mode = VariableMode::Immediate
+            }
             Rule::variable_add => {
                 var.1.mode = VariableMode::Add
             }
@@ -306,9 +344,18 @@ fn parse_variable(variable_pair: Pairs<crate::Rule>, m: &mut Makefile) -> Result
                     VariableMode::Set => {
                         var.1.values.push(p.as_str().to_string());
                     }
+                    VariableMode::Immediate => {
+                        // Expand against what is defined so far. References to variables
+                        // not known yet (e.g. from includes) are kept for lazy resolution.
+                        let value = MakefileVariable {
+                            values: vec![p.as_str().to_string()],
+                            ..MakefileVariable::default()
+                        };
+                        var.1.values.append(&mut m.resolve_nested_variables(&value));
+                    }
                 }
             }
-            _ => panic!("unexpected rule {:?} inside makefile rule expected variable_name, variable_set, variable_add, variable_value", p.as_rule()),
+            _ => panic!("unexpected rule {:?} inside makefile rule expected variable_name, variable_set, variable_immediate, variable_add, variable_value", p.as_rule()),
         }
     }
     m.variables.insert(var.0, var.1);
@@ -318,8 +365,124 @@ fn parse_variable(variable_pair: Pairs<crate::Rule>, m: &mut Makefile) -> Result
 
 #[cfg(test)]
 mod tests {
+    use crate::{Makefile, VariableMode};
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn immediate_and_deferred_expansion() {
+        let m = Makefile::parse_string(String::from(
+            "B := y
+A := $(B)
+C = $(B)
+B := x
+",
+        ))
+        .unwrap();
+
+        assert_eq!(m.variables["A"].mode, VariableMode::Immediate);
+        assert_eq!(m.variables["C"].mode, VariableMode::Set);
+        assert_eq!(m.get("A"), Some("y".to_string()));
+        assert_eq!(m.get("C"), Some("x".to_string()));
+    }
+
+    #[test]
+    fn append_to_variable() {
+        let m = Makefile::parse_string(String::from(
+            "CONFIGURE_OPTIONS = --prefix=/usr
+CONFIGURE_OPTIONS += --disable-static
+",
+        ))
+        .unwrap();
+
+        assert_eq!(
+            m.variables["CONFIGURE_OPTIONS"].values,
+            vec!["--prefix=/usr".to_string(), "--disable-static".to_string()]
+        );
+    }
+
+    #[test]
+    fn substitution_reference() {
+        let m = Makefile::parse_string(String::from(
+            "SRCS = a.c b.c
+OBJS = $(SRCS:.c=.o)
+LIBS = $(SRCS:%.c=lib%.so)
+",
+        ))
+        .unwrap();
+
+        assert_eq!(m.get("OBJS"), Some("a.o b.o".to_string()));
+        assert_eq!(m.get("LIBS"), Some("liba.so libb.so".to_string()));
+    }
+
+    // Serves body once on a local port and returns the url of the archive
+    fn serve_archive(body: &'static [u8]) -> (String, std::thread::JoinHandle<()>) {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            stream.write_all(header.as_bytes()).unwrap();
+            stream.write_all(body).unwrap();
+        });
+        (format!("http://{}/hello-1.0.tar.gz", addr), server)
+    }
+
+    #[test]
+    fn fetch_and_verify_archive() {
+        use crate::sources::{fetch_archive, verify_archive_hash};
+
+        let (url, server) = serve_archive(b"hello\n");
+        let data = fetch_archive(&url).unwrap();
+        server.join().unwrap();
+        assert_eq!(data, b"hello\n");
+
+        let hash = "sha256:5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03";
+        assert!(verify_archive_hash(&data, hash).unwrap());
+        assert!(!verify_archive_hash(b"tampered\n", hash).unwrap());
+        assert!(verify_archive_hash(&data, "md5:b1946ac92492d2347c6235b4d2611184").is_err());
+    }
+
+    #[test]
+    fn fetch_component_source() {
+        use crate::sources::fetch_verified_archive;
+
+        let makefile = Makefile::parse_string(String::from(
+            "COMPONENT_ARCHIVE_HASH= \
+    sha256:5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03\n",
+        ))
+        .unwrap();
+
+        let (url, server) = serve_archive(b"hello\n");
+        let res = fetch_verified_archive(&makefile, &url);
+        server.join().unwrap();
+        assert!(res.is_ok(), "error fetching archive: {:?}", res);
+        assert_eq!(res.unwrap(), b"hello\n");
+
+        let (url, server) = serve_archive(b"tampered\n");
+        let res = fetch_verified_archive(&makefile, &url);
+        server.join().unwrap();
+        assert_eq!(
+            res.unwrap_err().to_string(),
+            format!(
+                "hash mismatch for {}, expected \
+                 sha256:5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03",
+                url
+            )
+        );
+
+        let without_hash = Makefile::parse_string(String::from("COMPONENT_NAME=hello\n")).unwrap();
+        assert!(fetch_verified_archive(&without_hash, &url).is_err());
+    }
 }
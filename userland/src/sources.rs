@@ -0,0 +1,43 @@
+use crate::Makefile;
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use url::Url;
+
+pub fn fetch_archive(url: &str) -> Result<Vec<u8>> {
+    let url = Url::parse(url)?;
+    let mut response = reqwest::blocking::get(url)?.error_for_status()?;
+    let mut data = Vec::new();
+    response.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+// COMPONENT_ARCHIVE_HASH has the form sha256:<hex>, a bare hash is taken to
+// be sha256 as well
+pub fn verify_archive_hash(data: &[u8], expected: &str) -> Result<bool> {
+    let expected = expected.trim();
+    let hash = match expected.split_once(':') {
+        Some(("sha256", hash)) => hash,
+        Some((algo, _)) => return Err(anyhow!("unsupported archive hash algorithm {}", algo)),
+        None => expected,
+    };
+    let actual = format!("{:x}", Sha256::digest(data));
+    Ok(actual.eq_ignore_ascii_case(hash))
+}
+
+// Downloads the archive of a component from url and checks it against the
+// COMPONENT_ARCHIVE_HASH of its Makefile. A mismatch is an error.
+pub fn fetch_verified_archive(makefile: &Makefile, url: &str) -> Result<Vec<u8>> {
+    let hash = makefile
+        .get("COMPONENT_ARCHIVE_HASH")
+        .ok_or_else(|| anyhow!("component has no COMPONENT_ARCHIVE_HASH"))?;
+    let data = fetch_archive(url)?;
+    if !verify_archive_hash(&data, &hash)? {
+        return Err(anyhow!(
+            "hash mismatch for {}, expected {}",
+            url,
+            hash.trim()
+        ));
+    }
+    Ok(data)
+}